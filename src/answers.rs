@@ -0,0 +1,96 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{new_error, ErrorKind, Result};
+use crate::utils::{read_file, write_file};
+use crate::Value;
+
+/// The name of the file where the answers are recorded in the output directory
+pub const ANSWERS_FILENAME: &str = ".kickstart-answers.toml";
+
+/// Everything needed to generate a project again: which template was used and the values
+/// of its variables.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedAnswers {
+    /// Where the template was loaded from: a local path, an archive or a git URL
+    pub source: String,
+    /// The directory of the template in its source
+    pub directory: Option<String>,
+    /// The commit the template was at if it comes from a git repository
+    pub revision: Option<String>,
+    /// The version of the template, from its template.toml
+    pub version: Option<String>,
+    /// The values of all the variables
+    pub variables: BTreeMap<String, Value>,
+}
+
+impl RecordedAnswers {
+    /// Reads an answers file written by [RecordedAnswers::write]
+    pub fn from_file(path: &Path) -> Result<RecordedAnswers> {
+        toml::from_str(&read_file(path)?).map_err(|err| new_error(ErrorKind::Toml { err }))
+    }
+
+    /// Writes the answers file in the given directory and returns its path
+    pub fn write(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join(ANSWERS_FILENAME);
+        let content = toml::to_string(self).expect("to serialize recorded answers");
+        write_file(&path, &content)?;
+        Ok(path)
+    }
+}
+
+/// Reads the values of variables from a TOML or JSON file, depending on its extension.
+/// The file is a flat map of variable name to value, eg `project_name = "hello"` in TOML.
+/// The values are not checked against the template, see [crate::Template::check_value].
+pub fn read_answers_file(path: &Path) -> Result<HashMap<String, Value>> {
+    let content = read_file(path)?;
+
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => {
+            serde_json::from_str(&content).map_err(|err| new_error(ErrorKind::Json { err }))
+        }
+        _ => toml::from_str(&content).map_err(|err| new_error(ErrorKind::Toml { err })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn can_read_toml_and_json_answers() {
+        let dir = tempdir().unwrap();
+        let toml_path = dir.path().join("answers.toml");
+        fs::write(&toml_path, "name = \"hello\"\ncount = 2\nenabled = true\nfeatures = [\"ci\"]\n")
+            .unwrap();
+        let json_path = dir.path().join("answers.json");
+        fs::write(
+            &json_path,
+            r#"{"name": "hello", "count": 2, "enabled": true, "features": ["ci"]}"#,
+        )
+        .unwrap();
+
+        for path in [toml_path, json_path] {
+            let answers = read_answers_file(&path).unwrap();
+            assert_eq!(answers.len(), 4);
+            assert_eq!(answers["name"], Value::String("hello".to_string()));
+            assert_eq!(answers["count"], Value::Integer(2));
+            assert_eq!(answers["enabled"], Value::Boolean(true));
+            assert_eq!(answers["features"], Value::Array(vec![Value::String("ci".to_string())]));
+        }
+    }
+
+    #[test]
+    fn errors_on_unsupported_values() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("answers.json");
+        fs::write(&path, r#"{"name": null}"#).unwrap();
+        assert!(matches!(read_answers_file(&path).unwrap_err().kind, ErrorKind::Json { .. }));
+    }
+}
@@ -0,0 +1,231 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use tempfile::NamedTempFile;
+
+use crate::errors::{map_io_err, new_error, ErrorKind, Result};
+use crate::output::{TarOutput, ZipOutput};
+use crate::plan::GenerationPlan;
+use crate::utils::create_directory;
+
+/// The archive formats a template can be loaded from
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum ArchiveFormat {
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Guess the format from the extension of the file
+    pub(crate) fn from_path(path: &Path) -> Option<ArchiveFormat> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+fn archive_error(path: &Path, reason: String) -> crate::errors::Error {
+    new_error(ErrorKind::Archive { path: path.to_path_buf(), reason })
+}
+
+/// Returns the path of an entry if it stays inside the extraction directory,
+/// eg it's not absolute and doesn't go up with `..`
+fn safe_entry_path(archive: &Path, entry: &Path) -> Result<PathBuf> {
+    let mut safe = PathBuf::new();
+    for component in entry.components() {
+        match component {
+            Component::Normal(c) => safe.push(c),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(archive_error(
+                    archive,
+                    format!(
+                        "entry `{}` would be extracted outside of the archive",
+                        entry.display()
+                    ),
+                ));
+            }
+        }
+    }
+    Ok(safe)
+}
+
+fn extract_tar_gz(archive: &Path, dest: &Path) -> Result<()> {
+    let file = map_io_err(File::open(archive), archive)?;
+    let mut tar = tar::Archive::new(GzDecoder::new(file));
+    let entries = tar.entries().map_err(|e| archive_error(archive, e.to_string()))?;
+
+    for entry in entries {
+        let mut entry = entry.map_err(|e| archive_error(archive, e.to_string()))?;
+        let entry_path = entry.path().map_err(|e| archive_error(archive, e.to_string()))?;
+        let relative = safe_entry_path(archive, &entry_path)?;
+
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            // Links are only allowed if they point somewhere inside the archive
+            let target = entry
+                .link_name()
+                .map_err(|e| archive_error(archive, e.to_string()))?
+                .unwrap_or_default();
+            let parent = relative.parent().unwrap_or_else(|| Path::new(""));
+            let resolved =
+                if entry_type.is_hard_link() { target.to_path_buf() } else { parent.join(&target) };
+            if target.is_absolute() || !stays_inside(&resolved) {
+                return Err(archive_error(
+                    archive,
+                    format!("link `{}` points outside of the archive", relative.display()),
+                ));
+            }
+        }
+
+        // `unpack_in` also refuses paths going outside of `dest`
+        entry.unpack_in(dest).map_err(|e| archive_error(archive, e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+/// Whether a relative path never goes above its root
+pub(crate) fn stays_inside(path: &Path) -> bool {
+    let mut depth = 0i32;
+    for component in path.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => {
+                depth -= 1;
+                if depth < 0 {
+                    return false;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+fn extract_zip(archive: &Path, dest: &Path) -> Result<()> {
+    let file = map_io_err(File::open(archive), archive)?;
+    let mut zip = zip::ZipArchive::new(file).map_err(|e| archive_error(archive, e.to_string()))?;
+
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(|e| archive_error(archive, e.to_string()))?;
+        let relative = safe_entry_path(archive, Path::new(entry.name()))?;
+        if entry.is_symlink() {
+            return Err(archive_error(
+                archive,
+                format!("symlink `{}` is not supported in zip archives", relative.display()),
+            ));
+        }
+        let out_path = dest.join(&relative);
+
+        if entry.is_dir() {
+            create_directory(&out_path)?;
+            continue;
+        }
+        if let Some(parent) = out_path.parent() {
+            create_directory(parent)?;
+        }
+        let mut out = map_io_err(File::create(&out_path), &out_path)?;
+        map_io_err(io::copy(&mut entry, &mut out), &out_path)?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if let Some(mode) = entry.unix_mode() {
+                let permissions = fs::Permissions::from_mode(mode & 0o777);
+                map_io_err(fs::set_permissions(&out_path, permissions), &out_path)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Extracts the archive in `dest` and returns the root of the template: archives made of
+/// a single top-level directory, like most release archives, use that directory.
+pub(crate) fn extract(archive: &Path, format: ArchiveFormat, dest: &Path) -> Result<PathBuf> {
+    match format {
+        ArchiveFormat::TarGz => extract_tar_gz(archive, dest)?,
+        ArchiveFormat::Zip => extract_zip(archive, dest)?,
+    }
+
+    if dest.join("template.toml").exists() {
+        return Ok(dest.to_path_buf());
+    }
+    let mut children = Vec::new();
+    for entry in map_io_err(fs::read_dir(dest), dest)? {
+        children.push(map_io_err(entry, dest)?.path());
+    }
+    match children.as_slice() {
+        [single] if single.is_dir() => Ok(single.clone()),
+        _ => Ok(dest.to_path_buf()),
+    }
+}
+
+/// Writes the plan in an archive, whose format is guessed from its extension.
+/// The archive is only created once everything was written successfully.
+pub(crate) fn create(plan: &GenerationPlan, path: &Path) -> Result<()> {
+    let format = ArchiveFormat::from_path(path).ok_or_else(|| {
+        new_error(ErrorKind::UnsupportedArchiveFormat { path: path.to_path_buf() })
+    })?;
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let file = map_io_err(NamedTempFile::new_in(parent), parent)?;
+
+    let file = match format {
+        ArchiveFormat::TarGz => {
+            let mut output = TarOutput::new(GzEncoder::new(file, Compression::default()));
+            plan.write_to(&mut output)?;
+            map_io_err(output.finish()?.finish(), path)?
+        }
+        ArchiveFormat::Zip => {
+            let mut output = ZipOutput::new(file);
+            plan.write_to(&mut output)?;
+            output.finish()?
+        }
+    };
+    map_io_err(file.persist(path).map_err(|e| e.error), path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_detect_archive_formats() {
+        let inputs = vec![
+            ("template.tar.gz", Some(ArchiveFormat::TarGz)),
+            ("template.TGZ", Some(ArchiveFormat::TarGz)),
+            ("some/dir/template.zip", Some(ArchiveFormat::Zip)),
+            ("template.tar", None),
+            ("template", None),
+        ];
+        for (input, expected) in inputs {
+            assert_eq!(ArchiveFormat::from_path(Path::new(input)), expected);
+        }
+    }
+
+    #[test]
+    fn refuses_unsafe_entries() {
+        let archive = Path::new("a.zip");
+        assert!(safe_entry_path(archive, Path::new("a/../../b")).is_err());
+        assert!(safe_entry_path(archive, Path::new("/etc/passwd")).is_err());
+        assert_eq!(safe_entry_path(archive, Path::new("./a/b")).unwrap(), PathBuf::from("a/b"));
+        assert!(stays_inside(Path::new("a/../b")));
+        assert!(!stays_inside(Path::new("a/../../b")));
+    }
+}
@@ -0,0 +1,258 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::errors::{map_io_err, new_error, ErrorKind, Result};
+use crate::git;
+use crate::utils::{create_directory, read_file, write_file};
+
+/// The file storing the metadata of a cache entry, next to its clone
+const ENTRY_FILENAME: &str = "entry.toml";
+/// The directory in which the repository is cloned in a cache entry
+const REPO_DIRNAME: &str = "repo";
+
+/// A remote template stored in the cache
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// The URL the template was cloned from
+    pub remote: String,
+    /// The branch, tag or commit requested, if any
+    pub git_ref: Option<String>,
+    /// When that entry was last used, as a UNIX timestamp in seconds
+    pub last_used: u64,
+    /// The directory of that entry in the cache
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+/// A local cache of the remote templates.
+/// Each remote/ref pair gets its own clone, which is fetched again on each use
+/// unless the cache is offline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cache {
+    root: PathBuf,
+    offline: bool,
+}
+
+impl Cache {
+    /// Create a cache storing its entries in the given directory
+    pub fn new<T: Into<PathBuf>>(root: T) -> Cache {
+        Cache { root: root.into(), offline: false }
+    }
+
+    /// The default cache: `$KICKSTART_CACHE_DIR` if set, the user cache directory otherwise.
+    pub fn default_location() -> Cache {
+        if let Some(dir) = env::var_os("KICKSTART_CACHE_DIR") {
+            return Cache::new(dir);
+        }
+        let base = dirs::cache_dir().unwrap_or_else(env::temp_dir);
+        Cache::new(base.join("kickstart"))
+    }
+
+    /// When offline, only the templates already in the cache can be used and nothing
+    /// is fetched.
+    pub fn offline(mut self, offline: bool) -> Cache {
+        self.offline = offline;
+        self
+    }
+
+    /// The directory where the entries are stored
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The cache key of a remote/ref pair: the last segment of the URL to keep it readable,
+    /// followed by a hash of the full URL and ref.
+    fn key(remote: &str, git_ref: Option<&str>) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(remote.as_bytes());
+        hasher.update([0]);
+        hasher.update(git_ref.unwrap_or("").as_bytes());
+        let hash: String = hasher.finalize().iter().take(8).map(|b| format!("{:02x}", b)).collect();
+
+        let name = remote.trim_end_matches('/').rsplit(['/', ':']).next().unwrap_or("");
+        let name: String = name
+            .trim_end_matches(".git")
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+            .collect();
+        if name.is_empty() {
+            hash
+        } else {
+            format!("{name}-{hash}")
+        }
+    }
+
+    /// Makes sure the remote is in the cache at the right revision and returns the path
+    /// to its clone.
+    /// The repository is cloned the first time and only fetched afterwards.
+    /// `HEAD` is the default branch, like no ref, and shares its entry.
+    /// Only the first clone is safe to run concurrently: fetching and checking out an existing
+    /// entry is not locked, so runs using the same remote and ref at the same time can check
+    /// it out under each other.
+    pub(crate) fn fetch(&self, remote: &str, git_ref: Option<&str>) -> Result<PathBuf> {
+        let git_ref = git_ref.filter(|r| *r != "HEAD");
+        let entry_dir = self.root.join(Cache::key(remote, git_ref));
+        let repo = entry_dir.join(REPO_DIRNAME);
+
+        if repo.join(".git").exists() {
+            if !self.offline {
+                git::fetch(&repo)?;
+            }
+        } else {
+            if self.offline {
+                return Err(new_error(ErrorKind::NotCached { remote: remote.to_string() }));
+            }
+            self.clone_entry(remote, &repo)?;
+        }
+
+        // `HEAD` resolves to `origin/HEAD`, the default branch of the remote
+        git::checkout(&repo, git_ref.unwrap_or("HEAD"))?;
+
+        let entry = CacheEntry {
+            remote: remote.to_string(),
+            git_ref: git_ref.map(|r| r.to_string()),
+            last_used: now(),
+            path: entry_dir.clone(),
+        };
+        let serialized = toml::to_string(&entry).expect("to serialize a cache entry");
+        write_file(&entry_dir.join(ENTRY_FILENAME), &serialized)?;
+
+        Ok(repo)
+    }
+
+    /// Clones the remote in a temporary directory of the cache and only then moves it to
+    /// `repo`, so concurrent first clones never use or remove a partial clone.
+    fn clone_entry(&self, remote: &str, repo: &Path) -> Result<()> {
+        create_directory(&self.root)?;
+        let tmp = map_io_err(
+            tempfile::Builder::new().prefix(".clone-").tempdir_in(&self.root),
+            &self.root,
+        )?;
+        let cloned = tmp.path().join(REPO_DIRNAME);
+        git::clone(remote, &cloned)?;
+
+        let entry_dir = repo.parent().expect("the repository to be in an entry");
+        create_directory(entry_dir)?;
+        if repo.exists() && !repo.join(".git").exists() {
+            // Leftover from an interrupted clone of an older version
+            map_io_err(fs::remove_dir_all(repo), repo)?;
+        }
+        if let Err(err) = fs::rename(&cloned, repo) {
+            // Another run filled that entry in the meantime, we can use its clone
+            if !repo.join(".git").exists() {
+                return map_io_err(Err(err), repo);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// All the entries in the cache, sorted by remote
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        let mut entries = Vec::new();
+        if !self.root.exists() {
+            return Ok(entries);
+        }
+
+        for dir_entry in map_io_err(fs::read_dir(&self.root), &self.root)? {
+            let path = map_io_err(dir_entry, &self.root)?.path();
+            let entry_path = path.join(ENTRY_FILENAME);
+            if !entry_path.exists() {
+                continue;
+            }
+            let mut entry: CacheEntry = toml::from_str(&read_file(&entry_path)?)
+                .map_err(|err| new_error(ErrorKind::Toml { err }))?;
+            entry.path = path;
+            entries.push(entry);
+        }
+        entries.sort_by(|a, b| (&a.remote, &a.git_ref).cmp(&(&b.remote, &b.git_ref)));
+
+        Ok(entries)
+    }
+
+    /// Removes the entries that haven't been used for at least `max_age` or all of them
+    /// if it is `None`, and returns the entries removed.
+    pub fn prune(&self, max_age: Option<Duration>) -> Result<Vec<CacheEntry>> {
+        let now = now();
+        let mut removed = Vec::new();
+
+        for entry in self.entries()? {
+            if let Some(age) = max_age {
+                if now.saturating_sub(entry.last_used) < age.as_secs() {
+                    continue;
+                }
+            }
+            map_io_err(fs::remove_dir_all(&entry.path), &entry.path)?;
+            removed.push(entry);
+        }
+
+        Ok(removed)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_keys_depend_on_full_url_and_ref() {
+        let a = Cache::key("https://github.com/a/template", None);
+        let b = Cache::key("https://github.com/b/template", None);
+        let c = Cache::key("https://github.com/a/template", Some("v1"));
+        assert!(a.starts_with("template-"));
+        assert_ne!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(a, Cache::key("https://github.com/a/template", None));
+        assert!(Cache::key("git@github.com:a/template.git", None).starts_with("template-"));
+    }
+
+    #[test]
+    fn can_list_and_prune_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+        assert!(cache.entries().unwrap().is_empty());
+
+        for (remote, last_used) in
+            [("https://example.com/old", 0), ("https://example.com/new", now())]
+        {
+            let entry_dir = dir.path().join(Cache::key(remote, None));
+            fs::create_dir_all(entry_dir.join(REPO_DIRNAME)).unwrap();
+            let entry = CacheEntry {
+                remote: remote.to_string(),
+                git_ref: None,
+                last_used,
+                path: entry_dir,
+            };
+            fs::write(entry.path.join(ENTRY_FILENAME), toml::to_string(&entry).unwrap()).unwrap();
+        }
+
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].remote, "https://example.com/new");
+
+        let removed = cache.prune(Some(Duration::from_secs(3600))).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].remote, "https://example.com/old");
+        assert!(!removed[0].path.exists());
+
+        cache.prune(None).unwrap();
+        assert!(cache.entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn offline_cache_errors_on_missing_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path()).offline(true);
+        let err = cache.fetch("https://example.com/template", None).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::NotCached { .. }));
+    }
+}
@@ -4,6 +4,7 @@ use crate::cli::terminal;
 use crate::errors::{new_error, ErrorKind, Result};
 use crate::Value;
 use regex::Regex;
+use semver::Version;
 
 /// Wait for user input and return what they typed
 fn read_line() -> Result<String> {
@@ -58,6 +59,38 @@ pub fn ask_string(prompt: &str, default: &str, validation: &Option<String>) -> R
     Ok(res)
 }
 
+/// Ask a question to the user where they can write any string without it being shown.
+/// The default is not shown either.
+pub fn ask_secret(prompt: &str, default: &str, validation: &Option<String>) -> Result<String> {
+    let shown_default = if default.is_empty() { "none" } else { "hidden" };
+    terminal::basic_question(prompt, &shown_default, validation);
+    let _ = io::stdout().flush();
+    // Without a terminal, eg when the input is piped, nothing is echoed anyway
+    let input = match rpassword::read_password() {
+        Ok(input) => input,
+        Err(_) => read_line()?,
+    };
+
+    let res = match &*input {
+        "" => default.to_string(),
+        _ => {
+            if let Some(ref pattern) = validation {
+                let re = Regex::new(pattern).unwrap();
+                if re.is_match(&input) {
+                    input
+                } else {
+                    terminal::error(&format!("The value needs to pass the regex: {}\n", pattern));
+                    ask_secret(prompt, default, validation)?
+                }
+            } else {
+                input
+            }
+        }
+    };
+
+    Ok(res)
+}
+
 /// Ask a question to the user where they can write an integer
 pub fn ask_integer(prompt: &str, default: i64) -> Result<i64> {
     terminal::basic_question(prompt, &default, &None);
@@ -78,6 +111,46 @@ pub fn ask_integer(prompt: &str, default: i64) -> Result<i64> {
     Ok(res)
 }
 
+/// Ask a question to the user where they can write a number
+pub fn ask_float(prompt: &str, default: f64) -> Result<f64> {
+    terminal::basic_question(prompt, &default, &None);
+    let _ = io::stdout().flush();
+    let input = read_line()?;
+
+    let res = match &*input {
+        "" => default,
+        _ => match input.parse::<f64>() {
+            Ok(f) if f.is_finite() => f,
+            _ => {
+                terminal::error(&format!("Invalid number: '{}'\n", input));
+                ask_float(prompt, default)?
+            }
+        },
+    };
+
+    Ok(res)
+}
+
+/// Ask a question to the user where they can write a semver version, eg `1.2.0`
+pub fn ask_version(prompt: &str, default: Version) -> Result<Version> {
+    terminal::basic_question(prompt, &default, &None);
+    let _ = io::stdout().flush();
+    let input = read_line()?;
+
+    let res = match &*input {
+        "" => default,
+        _ => match Version::parse(&input) {
+            Ok(v) => v,
+            Err(e) => {
+                terminal::error(&format!("Invalid version: '{}' ({})\n", input, e));
+                ask_version(prompt, default)?
+            }
+        },
+    };
+
+    Ok(res)
+}
+
 /// Ask users to make a choice between various options
 pub fn ask_choices(prompt: &str, default: &Value, choices: &[Value]) -> Result<Value> {
     terminal::bold(&format!("{}: \n", prompt));
@@ -121,3 +194,57 @@ pub fn ask_choices(prompt: &str, default: &Value, choices: &[Value]) -> Result<V
 
     Ok(res)
 }
+
+/// Ask users to select any number of options, the selected ones being shown with a `x`
+pub fn ask_multi_choices(prompt: &str, default: &[Value], choices: &[Value]) -> Result<Vec<Value>> {
+    terminal::bold(&format!("{}: \n", prompt));
+    let mut default_indices = vec![];
+
+    for (index, choice) in choices.iter().enumerate() {
+        let selected = default.contains(choice);
+        terminal::bold(&format!(
+            "  [{}] {}. {}\n",
+            if selected { "x" } else { " " },
+            index + 1,
+            choice
+        ));
+        if selected {
+            default_indices.push((index + 1).to_string());
+        }
+    }
+
+    let default_display =
+        if default_indices.is_empty() { "none".to_string() } else { default_indices.join(",") };
+    terminal::basic_question(
+        &format!("  > Choose any of {}..{}, separated by commas, or none", 1, choices.len()),
+        &default_display,
+        &None,
+    );
+
+    let _ = io::stdout().flush();
+    let input = read_line()?;
+
+    let res = match input.trim() {
+        "" => default.to_vec(),
+        "none" => vec![],
+        trimmed => {
+            let mut selected = vec![];
+            for part in trimmed.split(',').map(|p| p.trim()) {
+                match part.parse::<usize>() {
+                    Ok(num) if num >= 1 && num <= choices.len() => {
+                        if !selected.contains(&choices[num - 1]) {
+                            selected.push(choices[num - 1].clone());
+                        }
+                    }
+                    _ => {
+                        terminal::error(&format!("Invalid choice: '{}'\n", part));
+                        return ask_multi_choices(prompt, default, choices);
+                    }
+                }
+            }
+            selected
+        }
+    };
+
+    Ok(res)
+}
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::errors::{new_error, ErrorKind, Result};
+use crate::utils::read_file;
+
+/// A short name for a template, defined in the user config
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateAlias {
+    /// Where the template is: a local path, an archive or a git URL
+    pub url: String,
+    /// The branch, tag or commit to use for git templates
+    #[serde(rename = "ref")]
+    pub git_ref: Option<String>,
+    /// The directory of the template in the repository
+    pub directory: Option<String>,
+}
+
+/// The user configuration, usually loaded from `~/.config/kickstart/config.toml`:
+///
+/// ```toml
+/// [templates.rust-cli]
+/// url = "https://github.com/Keats/rust-cli-template"
+/// ref = "v1.0.0"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Templates that can be used by their name rather than their full URL
+    #[serde(default)]
+    pub templates: BTreeMap<String, TemplateAlias>,
+}
+
+impl Config {
+    /// Where the user config is: `$KICKSTART_CONFIG` if set,
+    /// `kickstart/config.toml` in the user config directory otherwise.
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os("KICKSTART_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+        dirs::config_dir().map(|d| d.join("kickstart").join("config.toml"))
+    }
+
+    /// Load the config at the default path, see [Config::default_path].
+    /// Not having a config file is not an error and returns an empty config.
+    pub fn load() -> Result<Config> {
+        match Config::default_path() {
+            Some(path) if path.exists() => Config::from_file(&path),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    /// Load the config from the given file.
+    /// Relative paths in the aliases are relative to the directory of that file.
+    pub fn from_file(path: &Path) -> Result<Config> {
+        let mut config: Config =
+            toml::from_str(&read_file(path)?).map_err(|err| new_error(ErrorKind::Toml { err }))?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        for alias in config.templates.values_mut() {
+            // Anything with a `:` is a URL, eg `https://`, `file://` or `git@github.com:`
+            if !alias.url.contains(':') && Path::new(&alias.url).is_relative() {
+                alias.url = format!("{}", base.join(&alias.url).display());
+            }
+        }
+        Ok(config)
+    }
+
+    /// Returns the alias with that name, if there is one
+    pub fn get_alias(&self, name: &str) -> Option<&TemplateAlias> {
+        self.templates.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_load_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [templates.cli]
+            url = "https://github.com/Keats/rust-cli-template"
+            ref = "v1"
+
+            [templates.complex]
+            url = "https://github.com/Keats/kickstart"
+            directory = "examples/complex"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.templates.len(), 2);
+        let cli = config.get_alias("cli").unwrap();
+        assert_eq!(cli.git_ref.as_deref(), Some("v1"));
+        assert_eq!(cli.directory, None);
+        let complex = config.get_alias("complex").unwrap();
+        assert_eq!(complex.directory.as_deref(), Some("examples/complex"));
+        assert!(config.get_alias("nope").is_none());
+    }
+
+    #[test]
+    fn relative_paths_are_relative_to_the_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+            [templates.local]
+            url = "templates/basic"
+
+            [templates.absolute]
+            url = "/srv/templates/basic"
+
+            [templates.remote]
+            url = "git@github.com:Keats/rust-cli-template.git"
+            "#,
+        )
+        .unwrap();
+
+        let config = Config::from_file(&path).unwrap();
+        let url = |name: &str| config.get_alias(name).unwrap().url.clone();
+        assert_eq!(url("local"), format!("{}", dir.path().join("templates/basic").display()));
+        assert_eq!(url("absolute"), "/srv/templates/basic");
+        assert_eq!(url("remote"), "git@github.com:Keats/rust-cli-template.git");
+    }
+}
@@ -1,34 +1,236 @@
 use glob::Pattern;
 use regex::Regex;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt;
 use std::path::{Path, PathBuf};
 
 use serde::Deserialize;
 use tera::Context;
 
 use crate::errors::{new_error, ErrorKind, Result};
+use crate::expression::Expression;
+use crate::ignore::IgnorePatterns;
 use crate::utils::{read_file, render_one_off_template};
 use crate::Value;
 
-/// A condition for a question to be asked
-/// If the value is different or not found, the question should not be asked.
-/// Use [Template::should_ask_variable] rather than handling that yourself
+/// A condition for a question to be asked, a hook to be run or paths to be generated.
+/// It is either a single equality, `{ name = "database", value = "postgres" }`, or an
+/// expression such as `"database == 'postgres' and docker"`, see the README for its syntax.
+/// If a variable it uses was not set, eg because the question was not asked, the equality
+/// is false.
+/// Use [Condition::is_met] or [Template::should_ask_variable] rather than handling that yourself
 #[derive(Debug, Clone, PartialEq, Deserialize)]
-pub struct Condition {
-    pub name: String,
-    pub value: Value,
+#[serde(from = "RawCondition")]
+pub enum Condition {
+    Equals { name: String, value: Value },
+    Expression(String),
+}
+
+/// How conditions are written in TOML. The `only_if` form is used when the condition is
+/// inlined in a table with other fields, like in `cleanup`.
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum RawCondition {
+    Equals { name: String, value: Value },
+    Inline { only_if: String },
+    Expression(String),
+}
+
+impl From<RawCondition> for Condition {
+    fn from(raw: RawCondition) -> Condition {
+        match raw {
+            RawCondition::Equals { name, value } => Condition::Equals { name, value },
+            RawCondition::Inline { only_if } | RawCondition::Expression(only_if) => {
+                Condition::Expression(only_if)
+            }
+        }
+    }
+}
+
+impl Condition {
+    /// Whether the condition is true with those variables.
+    /// Errors if the expression is invalid, [TemplateDefinition::validate] reports them as well.
+    pub fn is_met(&self, vals: &HashMap<String, Value>) -> Result<bool> {
+        match self {
+            Condition::Equals { name, value } => Ok(vals.get(name) == Some(value)),
+            Condition::Expression(expr) => match Expression::parse(expr) {
+                Ok(e) => Ok(e.evaluate(vals)),
+                Err(reason) => {
+                    Err(new_error(ErrorKind::InvalidCondition { condition: expr.clone(), reason }))
+                }
+            },
+        }
+    }
+
+    /// The names of the variables used by the condition, or why its expression is invalid
+    pub(crate) fn variables(&self) -> std::result::Result<Vec<String>, String> {
+        match self {
+            Condition::Equals { name, .. } => Ok(vec![name.clone()]),
+            Condition::Expression(expr) => Ok(Expression::parse(expr)?
+                .variables()
+                .into_iter()
+                .map(|v| v.to_string())
+                .collect()),
+        }
+    }
 }
 
-/// A list of paths to be deleted when `name` has `value`
+/// A list of paths to be deleted when the condition is true
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct Cleanup {
-    pub name: String,
-    pub value: Value,
+    #[serde(flatten)]
+    pub condition: Condition,
+    pub paths: Vec<String>,
+}
+
+/// Glob patterns of files and directories to generate only if the condition is true, for
+/// `include_if`, or to skip if it is, for `exclude_if`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "RawConditionalPaths")]
+pub struct ConditionalPaths {
+    pub condition: Condition,
+    /// Glob patterns, rendered with Tera, matched against the paths in the output.
+    /// Everything in a matching directory is included/skipped as well.
     pub paths: Vec<String>,
 }
 
+/// How `include_if` and `exclude_if` are written in TOML: the condition is either `name` and
+/// `value` or an `only_if` expression
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConditionalPaths {
+    name: Option<String>,
+    value: Option<Value>,
+    only_if: Option<String>,
+    paths: Vec<String>,
+}
+
+impl TryFrom<RawConditionalPaths> for ConditionalPaths {
+    type Error = String;
+
+    fn try_from(raw: RawConditionalPaths) -> std::result::Result<ConditionalPaths, String> {
+        let condition = match (raw.name, raw.value, raw.only_if) {
+            (Some(name), Some(value), None) => Condition::Equals { name, value },
+            (None, None, Some(expr)) => Condition::Expression(expr),
+            _ => {
+                return Err("expected either `name` and `value`, or `only_if`".to_string());
+            }
+        };
+        Ok(ConditionalPaths { condition, paths: raw.paths })
+    }
+}
+
+/// The unix mode to give to the generated files matching `pattern`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Permission {
+    /// A glob pattern, rendered with Tera, matched against the path in the output
+    pub pattern: String,
+    /// The mode, eg `0o755`
+    pub mode: u32,
+}
+
+/// Checks that an expression condition outside of the variables, where all of them are known,
+/// is valid and only uses variables of the template
+fn expression_errors(section: &str, expr: &str, types: &HashMap<String, &str>) -> Vec<String> {
+    match Expression::parse(expr) {
+        Ok(expr) => expr
+            .variables()
+            .into_iter()
+            .filter(|n| !types.contains_key(*n))
+            .map(|n| format!("In {section}, `{n}` is not a variable of the template"))
+            .collect(),
+        Err(e) => vec![format!("In {section}, `{expr}` is not a valid condition: {e}")],
+    }
+}
+
+/// The type of a variable, for the types that can't be inferred from the default
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VariableType {
+    /// Allows integers as the default and choices, eg `default = 80`
+    Float,
+    /// A semver version, eg `default = "1.2.0"`
+    Version,
+}
+
+/// How variables are written in TOML
+#[derive(Deserialize)]
+struct RawVariable {
+    name: String,
+    default: Value,
+    prompt: String,
+    choices: Option<Vec<Value>>,
+    validation: Option<String>,
+    only_if: Option<Condition>,
+    #[serde(rename = "type")]
+    kind: Option<VariableType>,
+    min: Option<Value>,
+    max: Option<Value>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    #[serde(default)]
+    secret: bool,
+    env: Option<String>,
+}
+
+impl TryFrom<RawVariable> for Variable {
+    type Error = String;
+
+    fn try_from(raw: RawVariable) -> std::result::Result<Variable, String> {
+        let mut var = Variable {
+            name: raw.name,
+            default: raw.default,
+            prompt: raw.prompt,
+            choices: raw.choices,
+            validation: raw.validation,
+            only_if: raw.only_if,
+            min: raw.min,
+            max: raw.max,
+            min_length: raw.min_length,
+            max_length: raw.max_length,
+            secret: raw.secret,
+            env: raw.env,
+        };
+        // Bounds of floats can be integers and bounds of versions are strings
+        var.min = var.min.map(|v| v.coerce(&var.default));
+        var.max = var.max.map(|v| v.coerce(&var.default));
+
+        let Some(kind) = raw.kind else {
+            return Ok(var);
+        };
+        let like = match kind {
+            VariableType::Float => Value::Float(0.0),
+            VariableType::Version => Value::Version(semver::Version::new(0, 0, 0)),
+        };
+        var.default = var.default.coerce(&like);
+        var.choices = var.choices.map(|c| c.into_iter().map(|v| v.coerce(&like)).collect());
+        var.min = var.min.map(|v| v.coerce(&like));
+        var.max = var.max.map(|v| v.coerce(&like));
+
+        let values = match var.default {
+            Value::Array(ref items) => items.iter().collect::<Vec<_>>(),
+            ref v => vec![v],
+        };
+        for v in values.into_iter().chain(var.choices.iter().flatten()) {
+            if v.type_str() != like.type_str() {
+                return Err(format!(
+                    "Variable `{}` is a {} but `{}` is not one",
+                    var.name,
+                    like.type_str(),
+                    v
+                ));
+            }
+        }
+
+        Ok(var)
+    }
+}
+
 /// A question loaded from TOML
 #[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "RawVariable")]
 pub struct Variable {
     /// The variable name in the final context
     pub name: String,
@@ -36,12 +238,228 @@ pub struct Variable {
     pub(crate) default: Value,
     /// The text asked to the user
     pub prompt: String,
-    /// Only for questions with choices
+    /// Only for questions with choices. Questions with an array as default are multi-select
+    /// and must have choices: each selected value is one of them.
     pub choices: Option<Vec<Value>>,
     /// A regex pattern to validate the input. Only used where the value is meant to be a string.
     pub validation: Option<String>,
     /// Only ask this variable if that condition is true
     pub only_if: Option<Condition>,
+    /// The lowest value allowed, for integers, floats and versions
+    pub min: Option<Value>,
+    /// The highest value allowed, for integers, floats and versions
+    pub max: Option<Value>,
+    /// The minimum number of characters of strings, or of selected values of arrays
+    pub min_length: Option<usize>,
+    /// The maximum number of characters of strings, or of selected values of arrays
+    pub max_length: Option<usize>,
+    /// Secrets are read without being echoed, never recorded in answers files and never shown
+    /// in errors. Only strings can be secrets.
+    pub secret: bool,
+    /// The name of an environment variable to read the value from instead of asking for it
+    pub env: Option<String>,
+}
+
+impl Variable {
+    /// Checks that a value can be used for that variable: it needs to have the same type as
+    /// the default, be one of the choices if there are any (or only contain choices for arrays),
+    /// pass the validation regex if there is one and be within the bounds and lengths set.
+    pub fn check_value(&self, value: &Value) -> Result<()> {
+        let value = &self.coerce_value(value.clone());
+        let invalid = |reason: String| {
+            Err(new_error(ErrorKind::InvalidVariableValue { name: self.name.clone(), reason }))
+        };
+
+        if value.type_str() != self.default.type_str() {
+            return invalid(format!(
+                "expected a {} but got `{}`, which is a {}",
+                self.default.type_str(),
+                self.display_value(value),
+                value.type_str()
+            ));
+        }
+
+        if let (Some(choices), Some(items)) = (&self.choices, value.as_array()) {
+            if let Some(item) = items.iter().find(|i| !choices.contains(i)) {
+                let choices = choices.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+                return invalid(format!(
+                    "`{}` is not one of the choices: {}",
+                    item,
+                    choices.join(", ")
+                ));
+            }
+        } else if let Some(ref choices) = self.choices {
+            if !choices.contains(value) {
+                let choices = choices.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+                return invalid(format!(
+                    "`{}` is not one of the choices: {}",
+                    value,
+                    choices.join(", ")
+                ));
+            }
+        }
+
+        if let (Some(pattern), Some(s)) = (&self.validation, value.as_str()) {
+            match Regex::new(pattern) {
+                Ok(re) if re.is_match(s) => {}
+                Ok(_) => {
+                    return invalid(format!(
+                        "`{}` doesn't pass the regex: {}",
+                        self.display_value(&s),
+                        pattern
+                    ))
+                }
+                Err(_) => return invalid(format!("the validation regex is invalid: {}", pattern)),
+            }
+        }
+
+        self.check_bounds(value).or_else(invalid)
+    }
+
+    /// How a value is shown in errors, secrets are never shown
+    fn display_value(&self, value: &impl fmt::Display) -> String {
+        if self.secret {
+            "<secret>".to_string()
+        } else {
+            value.to_string()
+        }
+    }
+
+    /// The value of the environment variable set in `env`, parsed with [Variable::parse_value].
+    /// Returns `None` if there is no `env` or if the environment variable is not set.
+    pub fn value_from_env(&self) -> Result<Option<Value>> {
+        self.value_from(&|name| std::env::var(name).ok())
+    }
+
+    /// Same as [Variable::value_from_env] but looking up the environment variable with `env`
+    pub(crate) fn value_from(&self, env: &dyn Fn(&str) -> Option<String>) -> Result<Option<Value>> {
+        let Some(ref name) = self.env else {
+            return Ok(None);
+        };
+        match env(name) {
+            Some(input) => Ok(Some(self.parse_value(&input)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Checks `min`, `max`, `min_length` and `max_length`, returning why the value is invalid
+    fn check_bounds(&self, value: &Value) -> std::result::Result<(), String> {
+        if let Some(ref min) = self.min {
+            if value.order(min) == Some(Ordering::Less) {
+                return Err(format!("`{}` is lower than the minimum, {}", value, min));
+            }
+        }
+        if let Some(ref max) = self.max {
+            if value.order(max) == Some(Ordering::Greater) {
+                return Err(format!("`{}` is greater than the maximum, {}", value, max));
+            }
+        }
+
+        let (length, unit) = match value {
+            Value::String(s) => (s.chars().count(), "characters"),
+            Value::Array(a) => (a.len(), "values"),
+            _ => return Ok(()),
+        };
+        if let Some(min_length) = self.min_length.filter(|m| length < *m) {
+            return Err(format!(
+                "`{}` has {} {} but at least {} are required",
+                self.display_value(value),
+                length,
+                unit,
+                min_length
+            ));
+        }
+        if let Some(max_length) = self.max_length.filter(|m| length > *m) {
+            return Err(format!(
+                "`{}` has {} {} but at most {} are allowed",
+                self.display_value(value),
+                length,
+                unit,
+                max_length
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Converts a value to the type of the variable when it is a version or a float, since
+    /// they are strings and possibly integers in answers files. Other values are returned as is.
+    pub fn coerce_value(&self, value: Value) -> Value {
+        match self.default {
+            Value::Array(_) => match self.choices.as_deref() {
+                Some([first, ..]) => value.coerce(first),
+                _ => value,
+            },
+            ref like => value.coerce(like),
+        }
+    }
+
+    /// Parses a value given as a string, eg from the command line, according to the type of
+    /// the default value and checks it with [Variable::check_value].
+    /// Arrays are given as comma-separated values, eg `docker,ci`, of the type of the choices.
+    pub fn parse_value(&self, input: &str) -> Result<Value> {
+        let value = self.parse_scalar(input, &self.default)?;
+        self.check_value(&value)?;
+        Ok(value)
+    }
+
+    fn parse_scalar(&self, input: &str, like: &Value) -> Result<Value> {
+        let value = match like {
+            Value::Array(_) => {
+                let item_like = match self.choices.as_deref() {
+                    Some([first, ..]) => first,
+                    _ => &Value::String(String::new()),
+                };
+                let items = input
+                    .split(',')
+                    .map(|i| i.trim())
+                    .filter(|i| !i.is_empty())
+                    .map(|i| self.parse_scalar(i, item_like))
+                    .collect::<Result<_>>()?;
+                Value::Array(items)
+            }
+            Value::String(_) => Value::String(input.to_string()),
+            Value::Float(_) => match input.parse::<f64>() {
+                Ok(f) => Value::Float(f),
+                Err(_) => {
+                    return Err(new_error(ErrorKind::InvalidVariableValue {
+                        name: self.name.clone(),
+                        reason: format!("`{}` is not a number", input),
+                    }));
+                }
+            },
+            Value::Version(_) => match semver::Version::parse(input) {
+                Ok(v) => Value::Version(v),
+                Err(e) => {
+                    return Err(new_error(ErrorKind::InvalidVariableValue {
+                        name: self.name.clone(),
+                        reason: format!("`{}` is not a version: {}", input, e),
+                    }));
+                }
+            },
+            Value::Integer(_) => match input.parse::<i64>() {
+                Ok(i) => Value::Integer(i),
+                Err(_) => {
+                    return Err(new_error(ErrorKind::InvalidVariableValue {
+                        name: self.name.clone(),
+                        reason: format!("`{}` is not an integer", input),
+                    }));
+                }
+            },
+            Value::Boolean(_) => match input {
+                "y" | "Y" | "yes" | "YES" | "true" => Value::Boolean(true),
+                "n" | "N" | "no" | "NO" | "false" => Value::Boolean(false),
+                _ => {
+                    return Err(new_error(ErrorKind::InvalidVariableValue {
+                        name: self.name.clone(),
+                        reason: format!("`{}` is not a boolean", input),
+                    }));
+                }
+            },
+        };
+
+        Ok(value)
+    }
 }
 
 /// A hook is a file that will get executed
@@ -81,15 +499,33 @@ pub struct TemplateDefinition {
     /// Whether to follow symlinks
     #[serde(default)]
     pub follow_symlinks: bool,
-    /// Do not copy those directories/files
+    /// Whether to create the symlinks of the template in the output rather than following them
+    /// or reading them as files. Their target is rendered with Tera.
+    #[serde(default)]
+    pub recreate_symlinks: bool,
+    /// Do not copy those directories/files. Those are globs, which can be negated with `!`
     #[serde(default)]
     pub ignore: Vec<String>,
+    /// Also ignore the files matching the `.gitignore` files of the template, in addition to
+    /// the `.kickstartignore` ones which are always used
+    #[serde(default)]
+    pub respect_gitignore: bool,
     /// Conditionally delete some files/dirs based on generator values
     #[serde(default)]
     pub cleanup: Vec<Cleanup>,
+    /// Only generate some files/dirs if a variable has a given value
+    #[serde(default)]
+    pub include_if: Vec<ConditionalPaths>,
+    /// Do not generate some files/dirs if a variable has a given value
+    #[serde(default)]
+    pub exclude_if: Vec<ConditionalPaths>,
     /// Do not pass those files through Tera. Those can be globs
     #[serde(default)]
     pub copy_without_render: Vec<String>,
+    /// Modes to give to the generated files, the last matching pattern wins.
+    /// Files not matching any keep the mode they have in the template
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
     /// Hooks that should be ran after collecting all variables but before generating the template
     #[serde(default)]
     pub pre_gen_hooks: Vec<Hook>,
@@ -124,6 +560,29 @@ impl TemplateDefinition {
             }
         }
 
+        if self.follow_symlinks && self.recreate_symlinks {
+            errs.push(
+                "`follow_symlinks` and `recreate_symlinks` can't be both enabled".to_string(),
+            );
+        }
+
+        errs.extend(IgnorePatterns::validate(&self.ignore));
+
+        for permission in &self.permissions {
+            if let Err(e) = Pattern::new(&permission.pattern) {
+                errs.push(format!(
+                    "In permissions, `{}` is not a valid pattern: {e}",
+                    permission.pattern
+                ));
+            }
+            if permission.mode > 0o7777 {
+                errs.push(format!(
+                    "In permissions, `{:o}` is not a valid mode for `{}`",
+                    permission.mode, permission.pattern
+                ));
+            }
+        }
+
         for hook in self.all_hooks_paths() {
             let p = Path::new(&hook);
             if !p.exists() {
@@ -135,7 +594,45 @@ impl TemplateDefinition {
             let type_str = var.default.type_str();
             types.insert(var.name.to_string(), type_str);
 
-            if let Some(ref choices) = var.choices {
+            let defaults = match var.default {
+                Value::Array(ref items) => items.iter().collect::<Vec<_>>(),
+                ref v => vec![v],
+            };
+            for v in defaults.into_iter().chain(var.choices.iter().flatten()) {
+                if let Value::Float(f) = v {
+                    if !f.is_finite() {
+                        errs.push(format!(
+                            "Variable `{}` has `{}`, which is not a finite number",
+                            var.name, f
+                        ));
+                    }
+                }
+            }
+
+            if let Value::Array(ref items) = var.default {
+                match var.choices {
+                    Some(ref choices) => {
+                        for c in choices.iter().filter(|c| c.type_str() != choices[0].type_str()) {
+                            errs.push(format!(
+                                "Variable `{}` has choices of different types: `{}` is not a {}",
+                                var.name,
+                                c,
+                                choices[0].type_str()
+                            ));
+                        }
+                        for item in items.iter().filter(|i| !choices.contains(i)) {
+                            errs.push(format!(
+                                "Variable `{}` has `{}` in its default, which isn't in the choices",
+                                var.name, item
+                            ));
+                        }
+                    }
+                    None => errs.push(format!(
+                        "Variable `{}` has an array as default but no choices to select from",
+                        var.name
+                    )),
+                }
+            } else if let Some(ref choices) = var.choices {
                 let mut choice_found = false;
                 for c in choices {
                     if *c == var.default {
@@ -152,18 +649,102 @@ impl TemplateDefinition {
 
             // Since variables are ordered, we can detect whether the only_if is referring
             // to an unknown variable or a variable of the wrong type
-            if let Some(ref cond) = var.only_if {
-                if let Some(ref t) = types.get(&cond.name) {
-                    if **t != cond.value.type_str() {
+            match var.only_if {
+                Some(Condition::Equals { ref name, ref value }) => {
+                    if let Some(ref t) = types.get(name) {
+                        if **t != value.type_str() {
+                            errs.push(format!(
+                                "Variable `{}` depends on `{}={}`, but the type of `{}` is {}",
+                                var.name, name, value, name, t
+                            ));
+                        }
+                    } else {
                         errs.push(format!(
-                            "Variable `{}` depends on `{}={}`, but the type of `{}` is {}",
-                            var.name, cond.name, cond.value, cond.name, t
+                            "Variable `{}` depends on `{}`, which wasn't asked",
+                            var.name, name
                         ));
                     }
-                } else {
+                }
+                Some(ref cond @ Condition::Expression(ref expr)) => match cond.variables() {
+                    Ok(names) => {
+                        for name in names.iter().filter(|n| !types.contains_key(*n)) {
+                            errs.push(format!(
+                                "Variable `{}` depends on `{}`, which wasn't asked",
+                                var.name, name
+                            ));
+                        }
+                    }
+                    Err(e) => errs.push(format!(
+                        "Variable `{}` has an invalid only_if `{}`: {}",
+                        var.name, expr, e
+                    )),
+                },
+                None => {}
+            }
+
+            if var.secret {
+                if !var.default.is_str() {
+                    errs.push(format!("Variable `{}` is secret but is not a string", var.name));
+                }
+                if var.choices.is_some() {
+                    errs.push(format!("Variable `{}` is secret but has choices", var.name));
+                }
+            }
+
+            let mut valid_bounds = true;
+            for (key, bound) in [("min", &var.min), ("max", &var.max)] {
+                let Some(bound) = bound else { continue };
+                if !matches!(var.default, Value::Integer(_) | Value::Float(_) | Value::Version(_)) {
+                    errs.push(format!(
+                        "Variable `{}` has a {} but is a {}",
+                        var.name,
+                        key,
+                        var.default.type_str()
+                    ));
+                    valid_bounds = false;
+                } else if var.default.order(bound).is_none() {
+                    errs.push(format!(
+                        "Variable `{}` has `{} = {}`, which is not a {}",
+                        var.name,
+                        key,
+                        bound,
+                        var.default.type_str()
+                    ));
+                    valid_bounds = false;
+                }
+            }
+            for (key, length) in [("min_length", var.min_length), ("max_length", var.max_length)] {
+                if length.is_some() && !matches!(var.default, Value::String(_) | Value::Array(_)) {
+                    errs.push(format!(
+                        "Variable `{}` has a {} but is a {}",
+                        var.name,
+                        key,
+                        var.default.type_str()
+                    ));
+                    valid_bounds = false;
+                }
+            }
+            if let (Some(min), Some(max)) = (&var.min, &var.max) {
+                if min.order(max) == Some(Ordering::Greater) {
+                    errs.push(format!("Variable `{}` has a min greater than its max", var.name));
+                    valid_bounds = false;
+                }
+            }
+            if var.min_length > var.max_length && var.max_length.is_some() {
+                errs.push(format!(
+                    "Variable `{}` has a min_length greater than its max_length",
+                    var.name
+                ));
+                valid_bounds = false;
+            }
+            // Templated defaults are only known once rendered, which is checked when prompting
+            let templated =
+                matches!(var.default, Value::String(ref s) if s.contains("{{") || s.contains("{%"));
+            if valid_bounds && !templated {
+                if let Err(reason) = var.check_bounds(&var.default) {
                     errs.push(format!(
-                        "Variable `{}` depends on `{}`, which wasn't asked",
-                        var.name, cond.name
+                        "Variable `{}` has a default outside of its bounds: {}",
+                        var.name, reason
                     ));
                 }
             }
@@ -196,6 +777,46 @@ impl TemplateDefinition {
             }
         }
 
+        for (section, conditions) in
+            [("include_if", &self.include_if), ("exclude_if", &self.exclude_if)]
+        {
+            for cond in conditions {
+                match &cond.condition {
+                    Condition::Equals { name, value } => match types.get(name) {
+                        Some(t) if *t != value.type_str() => errs.push(format!(
+                            "In {section}, `{}` is compared to `{}` but the type of `{}` is {}",
+                            name, value, name, t
+                        )),
+                        Some(_) => {}
+                        None => errs.push(format!(
+                            "In {section}, `{}` is not a variable of the template",
+                            name
+                        )),
+                    },
+                    Condition::Expression(expr) => {
+                        errs.extend(expression_errors(section, expr, &types))
+                    }
+                }
+                for pattern in &cond.paths {
+                    if let Err(e) = Pattern::new(pattern) {
+                        errs.push(format!("In {section}, `{pattern}` is not a valid pattern: {e}"));
+                    }
+                }
+            }
+        }
+
+        for hook in self.pre_gen_hooks.iter().chain(&self.post_gen_hooks) {
+            if let Some(Condition::Expression(ref expr)) = hook.only_if {
+                errs.extend(expression_errors("hooks", expr, &types));
+            }
+        }
+
+        for cleanup in &self.cleanup {
+            if let Condition::Expression(ref expr) = cleanup.condition {
+                errs.extend(expression_errors("cleanup", expr, &types));
+            }
+        }
+
         errs
     }
 
@@ -213,14 +834,9 @@ impl TemplateDefinition {
     pub fn default_values(&self) -> Result<HashMap<String, Value>> {
         let mut vals = HashMap::new();
         for var in &self.variables {
-            // Skip the question if the value is different from the condition
+            // Skip the question if the condition is not met
             if let Some(ref cond) = var.only_if {
-                if let Some(val) = vals.get(&cond.name) {
-                    if *val != cond.value {
-                        continue;
-                    }
-                } else {
-                    // Not having it means we didn't even ask the question
+                if !cond.is_met(&vals)? {
                     continue;
                 }
             }
@@ -240,6 +856,15 @@ impl TemplateDefinition {
                 Value::Integer(i) => {
                     vals.insert(var.name.clone(), Value::Integer(*i));
                 }
+                Value::Float(f) => {
+                    vals.insert(var.name.clone(), Value::Float(*f));
+                }
+                Value::Version(v) => {
+                    vals.insert(var.name.clone(), Value::Version(v.clone()));
+                }
+                Value::Array(a) => {
+                    vals.insert(var.name.clone(), Value::Array(a.clone()));
+                }
             }
         }
 
@@ -249,14 +874,12 @@ impl TemplateDefinition {
 
 #[cfg(test)]
 mod tests {
-    use toml;
-
     use super::*;
 
     #[test]
     fn can_validate_definition() {
         insta::glob!("snapshots/validation/*.toml", |path| {
-            match TemplateDefinition::validate_file(&path) {
+            match TemplateDefinition::validate_file(path) {
                 Ok(errs) => insta::assert_debug_snapshot!(&errs),
                 Err(e) => insta::assert_snapshot!(&e),
             }
@@ -379,6 +1002,238 @@ mod tests {
         assert!(!res.contains_key("pg_bouncer"));
     }
 
+    #[test]
+    fn only_if_questions_can_use_expressions() {
+        let tpl: TemplateDefinition = toml::from_str(
+            r#"
+            name = "Test template"
+            description = "A description"
+            kickstart_version = 1
+
+            [[variables]]
+            name = "database"
+            default = "postgres"
+            prompt = "Which database to use?"
+            choices = ["postgres", "mysql", "sqlite"]
+
+            [[variables]]
+            name = "docker"
+            default = true
+            prompt = "Use Docker?"
+            only_if = "database != 'sqlite'"
+
+            [[variables]]
+            name = "pg_version"
+            prompt = "Which version of Postgres?"
+            default = 16
+            only_if = "database == 'postgres' and docker"
+
+            [[variables]]
+            name = "pg_bouncer"
+            prompt = "Add pgBouncer?"
+            default = true
+            only_if = "pg_version >= 17 or not docker"
+        "#,
+        )
+        .unwrap();
+
+        assert!(tpl.validate().is_empty());
+        let res = tpl.default_values().unwrap();
+        assert_eq!(res.get("pg_version"), Some(&Value::Integer(16)));
+        assert!(!res.contains_key("pg_bouncer"));
+    }
+
+    #[test]
+    fn can_check_values() {
+        let tpl: TemplateDefinition = toml::from_str(
+            r#"
+            name = "Test template"
+            kickstart_version = 1
+
+            [[variables]]
+            name = "project_name"
+            default = "my-project"
+            prompt = "What's the name of your project?"
+            validation = "^[a-z-]+$"
+
+            [[variables]]
+            name = "database"
+            default = "postgres"
+            prompt = "Which database to use?"
+            choices = ["postgres", "mysql"]
+
+            [[variables]]
+            name = "workers"
+            default = 4
+            prompt = "How many workers?"
+
+            [[variables]]
+            name = "features"
+            default = ["ci"]
+            prompt = "Which features?"
+            choices = ["ci", "docs", "docker"]
+            max_length = 2
+
+            [[variables]]
+            name = "coverage"
+            default = 80.0
+            prompt = "Minimum coverage?"
+            min = 0
+            max = 100
+
+            [[variables]]
+            name = "python_version"
+            default = "3.12.0"
+            prompt = "Which Python version?"
+            type = "version"
+            min = "3.9.0"
+
+            [[variables]]
+            name = "slug"
+            default = "app"
+            prompt = "Slug?"
+            min_length = 3
+            max_length = 8
+        "#,
+        )
+        .unwrap();
+
+        let selected = |items: &[&str]| {
+            Value::Array(items.iter().map(|i| Value::String(i.to_string())).collect())
+        };
+        let inputs = vec![
+            (0, Value::String("hello".to_string()), true),
+            (0, Value::String("Hello".to_string()), false),
+            (0, Value::Integer(1), false),
+            (1, Value::String("mysql".to_string()), true),
+            (1, Value::String("sqlite".to_string()), false),
+            (2, Value::Integer(8), true),
+            (2, Value::Boolean(true), false),
+            (3, selected(&["docs", "docker"]), true),
+            (3, selected(&[]), true),
+            (3, selected(&["docs", "lint"]), false),
+            (3, Value::String("docs".to_string()), false),
+            (3, selected(&["ci", "docs", "docker"]), false),
+            (4, Value::Float(100.0), true),
+            (4, Value::Float(100.5), false),
+            (4, Value::Integer(-1), false),
+            (5, Value::String("3.9.0".to_string()), true),
+            (5, Value::Version(semver::Version::new(3, 8, 10)), false),
+            (6, Value::String("api".to_string()), true),
+            (6, Value::String("db".to_string()), false),
+            (6, Value::String("backend-api".to_string()), false),
+        ];
+        for (index, value, is_ok) in inputs {
+            let res = tpl.variables[index].check_value(&value);
+            assert_eq!(res.is_ok(), is_ok, "{:?} for {}", res, value);
+            if let Err(e) = res {
+                assert!(matches!(e.kind, ErrorKind::InvalidVariableValue { .. }));
+            }
+        }
+    }
+
+    #[test]
+    fn secrets_are_not_shown_in_errors() {
+        let tpl: TemplateDefinition = toml::from_str(
+            r#"
+            name = "Test template"
+            kickstart_version = 1
+
+            [[variables]]
+            name = "db_password"
+            default = ""
+            prompt = "Database password?"
+            secret = true
+            min_length = 8
+        "#,
+        )
+        .unwrap();
+
+        let err = tpl.variables[0].check_value(&Value::String("hunter2".to_string())).unwrap_err();
+        assert!(!err.to_string().contains("hunter2"), "{}", err);
+        assert!(tpl.variables[0].value_from_env().unwrap().is_none());
+    }
+
+    #[test]
+    fn can_parse_values() {
+        let tpl: TemplateDefinition = toml::from_str(
+            r#"
+            name = "Test template"
+            kickstart_version = 1
+
+            [[variables]]
+            name = "database"
+            default = "postgres"
+            prompt = "Which database to use?"
+            choices = ["postgres", "mysql"]
+
+            [[variables]]
+            name = "workers"
+            default = 4
+            prompt = "How many workers?"
+
+            [[variables]]
+            name = "docker"
+            default = false
+            prompt = "Use docker?"
+
+            [[variables]]
+            name = "ports"
+            default = [80]
+            prompt = "Which ports to open?"
+            choices = [80, 443, 8080]
+
+            [[variables]]
+            name = "coverage"
+            default = 80
+            prompt = "Minimum coverage?"
+            type = "float"
+
+            [[variables]]
+            name = "python_version"
+            default = "3.12.0"
+            prompt = "Which Python version?"
+            type = "version"
+        "#,
+        )
+        .unwrap();
+
+        let inputs = vec![
+            (0, "mysql", Some(Value::String("mysql".to_string()))),
+            (0, "sqlite", None),
+            (1, "12", Some(Value::Integer(12))),
+            (1, "twelve", None),
+            (2, "yes", Some(Value::Boolean(true))),
+            (2, "false", Some(Value::Boolean(false))),
+            (2, "maybe", None),
+            (3, "443, 8080", Some(Value::Array(vec![Value::Integer(443), Value::Integer(8080)]))),
+            (3, "", Some(Value::Array(vec![]))),
+            (3, "22", None),
+            (3, "http", None),
+            (4, "92.5", Some(Value::Float(92.5))),
+            (4, "lots", None),
+            (
+                5,
+                "3.13.0-rc.1",
+                Some(Value::Version(semver::Version::parse("3.13.0-rc.1").unwrap())),
+            ),
+            (5, "3.13", None),
+        ];
+        for (index, input, expected) in inputs {
+            assert_eq!(tpl.variables[index].parse_value(input).ok(), expected, "{}", input);
+        }
+
+        assert_eq!(tpl.variables[4].default, Value::Float(80.0));
+        // Answers files have versions as strings
+        let python_version = &tpl.variables[5];
+        assert!(python_version.check_value(&Value::String("3.11.2".to_string())).is_ok());
+        assert!(python_version.check_value(&Value::String("latest".to_string())).is_err());
+        assert_eq!(
+            python_version.coerce_value(Value::String("3.11.2".to_string())),
+            Value::Version(semver::Version::new(3, 11, 2))
+        );
+    }
+
     #[test]
     fn use_previous_responses_in_default_value_with_variable_template() {
         let tpl: TemplateDefinition = toml::from_str(
@@ -32,9 +32,48 @@ pub enum ErrorKind {
     InvalidTemplate,
     UnreadableStdin,
     InvalidVariableName(String),
-    /// An error while cloning a repository
+    /// A value given for a variable doesn't have the right type, isn't one of its choices
+    /// or doesn't pass its validation
+    InvalidVariableValue {
+        name: String,
+        reason: String,
+    },
+    /// An error while loading a template from a git repository
     Git {
-        err: io::Error,
+        err: GitError,
+    },
+    /// The template is not in the cache and we are not allowed to fetch it
+    NotCached {
+        remote: String,
+    },
+    /// An archive couldn't be read or contains entries that would be extracted
+    /// outside of its directory
+    Archive {
+        path: PathBuf,
+        reason: String,
+    },
+    /// A condition of the template is not a valid expression
+    InvalidCondition {
+        condition: String,
+        reason: String,
+    },
+    /// The front matter of a template file couldn't be parsed or sets an invalid value
+    InvalidFrontMatter {
+        path: PathBuf,
+        reason: String,
+    },
+    /// A symlink of the template would point outside of the generated project
+    InvalidSymlink {
+        path: PathBuf,
+        target: PathBuf,
+    },
+    /// Projects can only be generated in `.tar.gz`, `.tgz` and `.zip` archives
+    UnsupportedArchiveFormat {
+        path: PathBuf,
+    },
+    /// Files of the output directory would be overwritten by the generation
+    Conflicts {
+        paths: Vec<PathBuf>,
     },
     /// An error while doing IO (reading/writing files)
     Io {
@@ -51,6 +90,10 @@ pub enum ErrorKind {
     Toml {
         err: toml::de::Error,
     },
+    /// An error while deserializing a JSON file
+    Json {
+        err: serde_json::Error,
+    },
     /// A glob pattern couldn't be built from the input
     InvalidGlobPattern {
         pattern_before_rendering: String,
@@ -59,6 +102,54 @@ pub enum ErrorKind {
     },
 }
 
+/// What went wrong when loading a template from a git repository
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum GitError {
+    /// The `git` command couldn't be found
+    NotInstalled,
+    /// The `git` command couldn't be started for another reason
+    Io(io::Error),
+    /// A git command exited with an error, eg the clone failed because of a bad URL
+    /// or an authentication failure
+    CommandFailed { command: String, status: Option<i32>, stderr: String },
+    /// The branch, tag or commit doesn't exist in the repository
+    UnknownRef(String),
+    /// The repository was cloned but there is no template.toml in it
+    MissingTemplateDefinition { remote: String, directory: Option<String> },
+}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GitError::NotInstalled => {
+                write!(f, "git is required to load remote templates but it wasn't found")
+            }
+            GitError::Io(err) => write!(f, "Could not run git: {}", err),
+            GitError::CommandFailed { command, status, stderr } => {
+                match status {
+                    Some(code) => write!(f, "`{}` failed with exit code {}", command, code)?,
+                    None => write!(f, "`{}` was terminated by a signal", command)?,
+                };
+                if stderr.is_empty() {
+                    Ok(())
+                } else {
+                    write!(f, ":\n{}", stderr)
+                }
+            }
+            GitError::UnknownRef(git_ref) => {
+                write!(f, "`{}` is not a branch, tag or commit of the repository", git_ref)
+            }
+            GitError::MissingTemplateDefinition { remote, directory } => match directory {
+                Some(dir) => {
+                    write!(f, "The repository {} has no template.toml in `{}`", remote, dir)
+                }
+                None => write!(f, "The repository {} has no template.toml", remote),
+            },
+        }
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
         new_error(ErrorKind::Io { err, path: PathBuf::new() })
@@ -82,8 +173,40 @@ impl fmt::Display for Error {
                     write!(f, "{}: rendering a one-off template", err)
                 }
             }
-            ErrorKind::Git { ref err } => write!(f, "Could not clone the repository: {}", err),
+            ErrorKind::Git { ref err } => write!(f, "{}", err),
+            ErrorKind::NotCached { ref remote } => write!(
+                f,
+                "The template `{}` is not in the cache: it needs to be loaded once while online",
+                remote
+            ),
+            ErrorKind::Archive { ref path, ref reason } => {
+                write!(f, "Could not extract the archive {:?}: {}", path, reason)
+            }
+            ErrorKind::InvalidCondition { ref condition, ref reason } => {
+                write!(f, "`{}` is not a valid condition: {}", condition, reason)
+            }
+            ErrorKind::InvalidFrontMatter { ref path, ref reason } => {
+                write!(f, "Invalid front matter in {:?}: {}", path, reason)
+            }
+            ErrorKind::InvalidSymlink { ref path, ref target } => write!(
+                f,
+                "The symlink {:?} points to {:?}, which is outside of the generated project",
+                path, target
+            ),
+            ErrorKind::UnsupportedArchiveFormat { ref path } => write!(
+                f,
+                "Unsupported archive format for {:?}: it needs to end with .tar.gz, .tgz or .zip",
+                path
+            ),
+            ErrorKind::Conflicts { ref paths } => {
+                write!(f, "{} file(s) of the output directory would be overwritten:", paths.len())?;
+                for path in paths {
+                    write!(f, "\n- {}", path.display())?;
+                }
+                Ok(())
+            }
             ErrorKind::Toml { ref err } => write!(f, "Invalid TOML: {}", err),
+            ErrorKind::Json { ref err } => write!(f, "Invalid JSON: {}", err),
             ErrorKind::InvalidGlobPattern {
                 ref err,
                 ref pattern_before_rendering,
@@ -102,6 +225,9 @@ impl fmt::Display for Error {
             ErrorKind::InvalidVariableName(ref name) => {
                 write!(f, "Variable {name} not found in the template definition")
             }
+            ErrorKind::InvalidVariableValue { ref name, ref reason } => {
+                write!(f, "Invalid value for variable {name}: {reason}")
+            }
             ErrorKind::MissingTemplateDefinition => write!(f, "The template.toml is missing"),
             ErrorKind::UnreadableStdin => write!(f, "Unable to read from stdin"),
             ErrorKind::InvalidTemplate => write!(f, "The template.toml is invalid"),
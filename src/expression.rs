@@ -0,0 +1,443 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::Value;
+
+/// A boolean expression used in conditions, eg `database == "postgres" and not docker`.
+///
+/// It supports:
+/// - `and`, `or`, `not` and parentheses
+/// - `==`, `!=`, `<`, `<=`, `>` and `>=`
+/// - `in` and `not in`, over lists like `["a", "b"]` and array variables, or as a substring
+///   check
+/// - strings, integers, floats, `true` and `false` literals
+///
+/// Integers and floats can be compared together, as well as versions and strings, which are
+/// parsed as versions.
+///
+/// Variables that are not set, eg questions that were not asked, make every comparison using
+/// them false and are falsy on their own.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expression {
+    Literal(Value),
+    Variable(String),
+    List(Vec<Expression>),
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Compare(Box<Expression>, Operator, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Operator {
+    Equal,
+    NotEqual,
+    Lower,
+    LowerOrEqual,
+    Greater,
+    GreaterOrEqual,
+    In,
+    NotIn,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Str(String),
+    Integer(i64),
+    Float(f64),
+    Operator(Operator),
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+    Comma,
+}
+
+/// What an expression evaluates to
+#[derive(Debug, Clone, PartialEq)]
+enum Evaluated {
+    Value(Value),
+    List(Vec<Evaluated>),
+    Undefined,
+}
+
+impl Evaluated {
+    /// Arrays become lists so they can be compared with list literals and used with `in`
+    fn from_value(value: &Value) -> Evaluated {
+        match value {
+            Value::Array(items) => {
+                Evaluated::List(items.iter().map(Evaluated::from_value).collect())
+            }
+            _ => Evaluated::Value(value.clone()),
+        }
+    }
+
+    fn is_truthy(&self) -> bool {
+        match self {
+            Evaluated::Value(Value::Boolean(b)) => *b,
+            Evaluated::Value(Value::Integer(i)) => *i != 0,
+            Evaluated::Value(Value::Float(f)) => *f != 0.0,
+            Evaluated::Value(Value::Version(_)) => true,
+            Evaluated::Value(Value::String(s)) => !s.is_empty(),
+            Evaluated::Value(Value::Array(l)) => !l.is_empty(),
+            Evaluated::List(l) => !l.is_empty(),
+            Evaluated::Undefined => false,
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | '[' | ']' | ',' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::OpenParen,
+                    ')' => Token::CloseParen,
+                    '[' => Token::OpenBracket,
+                    ']' => Token::CloseBracket,
+                    _ => Token::Comma,
+                });
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(ch) => s.push(ch),
+                        None => return Err(format!("unclosed string `{c}{s}`")),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let followed_by_equal = chars.next_if_eq(&'=').is_some();
+                let op = match (c, followed_by_equal) {
+                    ('=', true) => Operator::Equal,
+                    ('!', true) => Operator::NotEqual,
+                    ('<', false) => Operator::Lower,
+                    ('<', true) => Operator::LowerOrEqual,
+                    ('>', false) => Operator::Greater,
+                    ('>', true) => Operator::GreaterOrEqual,
+                    _ => return Err(format!("unknown operator `{c}`")),
+                };
+                tokens.push(Token::Operator(op));
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut s = String::new();
+                s.push(c);
+                chars.next();
+                while let Some(d) = chars.next_if(|d| d.is_ascii_digit() || *d == '.') {
+                    s.push(d);
+                }
+                if s.contains('.') {
+                    let f = s.parse().map_err(|_| format!("invalid number `{s}`"))?;
+                    tokens.push(Token::Float(f));
+                } else {
+                    let i = s.parse().map_err(|_| format!("invalid integer `{s}`"))?;
+                    tokens.push(Token::Integer(i));
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut s = String::new();
+                while let Some(d) = chars.next_if(|d| d.is_alphanumeric() || *d == '_') {
+                    s.push(d);
+                }
+                tokens.push(Token::Identifier(s));
+            }
+            _ => return Err(format!("unexpected character `{c}`")),
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// A recursive descent parser, from the lowest precedence (`or`) to the highest (literals)
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Identifier(s)) if s == keyword)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token, what: &str) -> Result<(), String> {
+        match self.next() {
+            Some(t) if t == expected => Ok(()),
+            _ => Err(format!("expected {what}")),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expression, String> {
+        let mut left = self.parse_and()?;
+        while self.peek_keyword("or") {
+            self.pos += 1;
+            left = Expression::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression, String> {
+        let mut left = self.parse_not()?;
+        while self.peek_keyword("and") {
+            self.pos += 1;
+            left = Expression::And(Box::new(left), Box::new(self.parse_not()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expression, String> {
+        if self.peek_keyword("not") {
+            self.pos += 1;
+            return Ok(Expression::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expression, String> {
+        let left = self.parse_primary()?;
+        let op = match self.peek() {
+            Some(Token::Operator(op)) => *op,
+            Some(Token::Identifier(s)) if s == "in" => Operator::In,
+            Some(Token::Identifier(s))
+                if s == "not"
+                    && matches!(self.tokens.get(self.pos + 1), Some(Token::Identifier(i)) if i == "in") =>
+            {
+                self.pos += 1;
+                Operator::NotIn
+            }
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.parse_primary()?;
+        Ok(Expression::Compare(Box::new(left), op, Box::new(right)))
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(Expression::Literal(Value::String(s))),
+            Some(Token::Integer(i)) => Ok(Expression::Literal(Value::Integer(i))),
+            Some(Token::Float(f)) => Ok(Expression::Literal(Value::Float(f))),
+            Some(Token::Identifier(s)) => match s.as_str() {
+                "true" => Ok(Expression::Literal(Value::Boolean(true))),
+                "false" => Ok(Expression::Literal(Value::Boolean(false))),
+                "and" | "or" | "not" | "in" => Err(format!("unexpected `{s}`")),
+                _ => Ok(Expression::Variable(s)),
+            },
+            Some(Token::OpenParen) => {
+                let expr = self.parse_or()?;
+                self.expect(Token::CloseParen, "`)`")?;
+                Ok(expr)
+            }
+            Some(Token::OpenBracket) => {
+                let mut items = Vec::new();
+                if self.peek() == Some(&Token::CloseBracket) {
+                    self.pos += 1;
+                    return Ok(Expression::List(items));
+                }
+                loop {
+                    items.push(self.parse_primary()?);
+                    match self.next() {
+                        Some(Token::Comma) => continue,
+                        Some(Token::CloseBracket) => break,
+                        _ => return Err("expected `,` or `]` in list".to_string()),
+                    }
+                }
+                Ok(Expression::List(items))
+            }
+            Some(t) => Err(format!("unexpected {t:?}")),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn order(left: &Evaluated, right: &Evaluated) -> Option<Ordering> {
+    match (left, right) {
+        (Evaluated::Value(left), Evaluated::Value(right)) => left.order(right),
+        _ => None,
+    }
+}
+
+fn equals(left: &Evaluated, right: &Evaluated) -> bool {
+    match order(left, right) {
+        Some(ordering) => ordering == Ordering::Equal,
+        None => left == right,
+    }
+}
+
+fn compare(left: &Evaluated, op: Operator, right: &Evaluated) -> bool {
+    if *left == Evaluated::Undefined || *right == Evaluated::Undefined {
+        return false;
+    }
+
+    match op {
+        Operator::Equal => equals(left, right),
+        Operator::NotEqual => !equals(left, right),
+        Operator::In | Operator::NotIn => {
+            let found = match (left, right) {
+                (_, Evaluated::List(items)) => items.iter().any(|i| equals(left, i)),
+                (
+                    Evaluated::Value(Value::String(needle)),
+                    Evaluated::Value(Value::String(haystack)),
+                ) => haystack.contains(needle.as_str()),
+                _ => return false,
+            };
+            found == (op == Operator::In)
+        }
+        _ => {
+            let Some(ordering) = order(left, right) else {
+                return false;
+            };
+            match op {
+                Operator::Lower => ordering == Ordering::Less,
+                Operator::LowerOrEqual => ordering != Ordering::Greater,
+                Operator::Greater => ordering == Ordering::Greater,
+                _ => ordering != Ordering::Less,
+            }
+        }
+    }
+}
+
+impl Expression {
+    pub(crate) fn parse(input: &str) -> Result<Expression, String> {
+        let mut parser = Parser { tokens: tokenize(input)?, pos: 0 };
+        let expr = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(t) => Err(format!("unexpected {t:?}")),
+        }
+    }
+
+    fn evaluate_inner(&self, vals: &HashMap<String, Value>) -> Evaluated {
+        match self {
+            Expression::Literal(v) => Evaluated::Value(v.clone()),
+            Expression::Variable(name) => {
+                vals.get(name).map(Evaluated::from_value).unwrap_or(Evaluated::Undefined)
+            }
+            Expression::List(items) => {
+                Evaluated::List(items.iter().map(|i| i.evaluate_inner(vals)).collect())
+            }
+            _ => Evaluated::Value(Value::Boolean(self.evaluate(vals))),
+        }
+    }
+
+    /// Whether the expression is true with those variables
+    pub(crate) fn evaluate(&self, vals: &HashMap<String, Value>) -> bool {
+        match self {
+            Expression::Not(e) => !e.evaluate(vals),
+            Expression::And(a, b) => a.evaluate(vals) && b.evaluate(vals),
+            Expression::Or(a, b) => a.evaluate(vals) || b.evaluate(vals),
+            Expression::Compare(a, op, b) => {
+                compare(&a.evaluate_inner(vals), *op, &b.evaluate_inner(vals))
+            }
+            _ => self.evaluate_inner(vals).is_truthy(),
+        }
+    }
+
+    /// The names of all the variables used in the expression
+    pub(crate) fn variables(&self) -> Vec<&str> {
+        match self {
+            Expression::Literal(_) => vec![],
+            Expression::Variable(name) => vec![name.as_str()],
+            Expression::List(items) => items.iter().flat_map(|i| i.variables()).collect(),
+            Expression::Not(e) => e.variables(),
+            Expression::And(a, b) | Expression::Or(a, b) | Expression::Compare(a, _, b) => {
+                let mut vars = a.variables();
+                vars.extend(b.variables());
+                vars
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_evaluate_expressions() {
+        let mut vals = HashMap::new();
+        vals.insert("database".to_string(), Value::String("postgres".to_string()));
+        vals.insert("docker".to_string(), Value::Boolean(true));
+        vals.insert("version".to_string(), Value::Integer(12));
+        vals.insert("coverage".to_string(), Value::Float(0.8));
+        vals.insert("python".to_string(), Value::Version(semver::Version::new(3, 12, 1)));
+        vals.insert(
+            "features".to_string(),
+            Value::Array(vec![Value::String("ci".to_string()), Value::String("docs".to_string())]),
+        );
+
+        let inputs = vec![
+            ("docker", true),
+            ("not docker", false),
+            ("database == 'postgres' and docker", true),
+            ("database == \"mysql\" or not docker", false),
+            ("database != 'mysql'", true),
+            ("version >= 10 and version < 13", true),
+            ("version > 12", false),
+            ("database in ['mysql', 'postgres']", true),
+            ("database not in ['mysql', 'sqlite']", true),
+            ("'gres' in database", true),
+            ("'ci' in features and 'lint' not in features", true),
+            ("features == ['ci', 'docs']", true),
+            ("features and not []", true),
+            ("not (docker and version == 12)", false),
+            ("docker or database == 'mysql' and version == 1", true),
+            ("version == -1", false),
+            ("coverage >= 0.75 and coverage < 1", true),
+            ("version == 12.0", true),
+            ("python >= '3.10.0' and python < '4.0.0'", true),
+            ("python == '3.12.1'", true),
+            ("python > 'not a version'", false),
+            // Undefined variables are falsy and never compare to anything
+            ("ci", false),
+            ("not ci", true),
+            ("ci == true", false),
+            ("ci != true", false),
+            ("ci in [true]", false),
+            // Values of different types are never equal
+            ("version == '12'", false),
+        ];
+
+        for (input, expected) in inputs {
+            let expr = Expression::parse(input).unwrap();
+            assert_eq!(expr.evaluate(&vals), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn can_list_variables() {
+        let expr = Expression::parse("(a or not b) and c in [d, 'e']").unwrap();
+        assert_eq!(expr.variables(), vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn errors_on_invalid_expressions() {
+        for input in
+            ["", "a ==", "a = 1", "(a and b", "a and or b", "'unclosed", "a b", "[a, b", "a & b"]
+        {
+            assert!(Expression::parse(input).is_err(), "{}", input);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::definition::Condition;
+use crate::errors::{new_error, ErrorKind, Result};
+
+const OPENING: &str = "+++ kickstart";
+const CLOSING: &str = "+++";
+
+/// Settings for a single file, given in a TOML block at the very top of that file.
+/// The block is removed from the output:
+///
+/// ```text
+/// +++ kickstart
+/// path = "{{ project_name }}/src/main.rs"
+/// mode = 0o755
+/// +++
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FrontMatter {
+    /// Where to write the file, relative to the output directory. Rendered with Tera
+    pub path: Option<String>,
+    /// Do not generate the file if that condition is true
+    pub skip_if: Option<Condition>,
+    /// Copy the rest of the file as is rather than rendering it
+    #[serde(default)]
+    pub copy_without_render: bool,
+    /// The unix mode of the generated file, eg `0o755`
+    pub mode: Option<u32>,
+}
+
+impl FrontMatter {
+    /// Splits the front matter of a file, if it has one, from the rest of its content.
+    /// `path` is only used for errors.
+    pub(crate) fn extract<'a>(
+        path: &Path,
+        content: &'a str,
+    ) -> Result<(Option<FrontMatter>, &'a str)> {
+        let Some(rest) = content.strip_prefix(OPENING) else {
+            return Ok((None, content));
+        };
+        let Some(rest) = rest.strip_prefix('\n').or_else(|| rest.strip_prefix("\r\n")) else {
+            return Ok((None, content));
+        };
+
+        let invalid = |reason: String| {
+            Err(new_error(ErrorKind::InvalidFrontMatter { path: path.to_path_buf(), reason }))
+        };
+
+        let mut offset = 0;
+        for line in rest.split_inclusive('\n') {
+            if line.trim_end() == CLOSING {
+                let front_matter: FrontMatter = match toml::from_str(&rest[..offset]) {
+                    Ok(f) => f,
+                    Err(e) => return invalid(e.to_string()),
+                };
+                // Same range as the modes of `permissions` in template.toml
+                if let Some(mode) = front_matter.mode.filter(|m| *m > 0o7777) {
+                    return invalid(format!("`{:o}` is not a valid mode", mode));
+                }
+                return Ok((Some(front_matter), &rest[offset + line.len()..]));
+            }
+            offset += line.len();
+        }
+
+        invalid(format!("the front matter is not closed with a `{}` line", CLOSING))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Value;
+
+    #[test]
+    fn can_extract_front_matter() {
+        let path = Path::new("run.sh");
+        let content = "+++ kickstart\npath = \"bin/run.sh\"\nmode = 0o755\nskip_if = { name = \"ci\", value = false }\n+++\n#!/bin/sh\n";
+        let (front_matter, rest) = FrontMatter::extract(path, content).unwrap();
+        let front_matter = front_matter.unwrap();
+        assert_eq!(front_matter.path.as_deref(), Some("bin/run.sh"));
+        assert_eq!(front_matter.mode, Some(0o755));
+        assert_eq!(
+            front_matter.skip_if,
+            Some(Condition::Equals { name: "ci".to_string(), value: Value::Boolean(false) })
+        );
+        assert!(!front_matter.copy_without_render);
+        assert_eq!(rest, "#!/bin/sh\n");
+
+        let (front_matter, rest) =
+            FrontMatter::extract(path, "+++\ntitle = \"hugo\"\n+++\n").unwrap();
+        assert!(front_matter.is_none());
+        assert_eq!(rest, "+++\ntitle = \"hugo\"\n+++\n");
+    }
+
+    #[test]
+    fn errors_on_invalid_front_matter() {
+        let path = Path::new("run.sh");
+        assert!(FrontMatter::extract(path, "+++ kickstart\nmode = 0o755\n").is_err());
+        assert!(FrontMatter::extract(path, "+++ kickstart\nunknown = true\n+++\n").is_err());
+        let err = FrontMatter::extract(path, "+++ kickstart\nmode = 0o10000\n+++\n").unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::InvalidFrontMatter { .. }));
+    }
+}
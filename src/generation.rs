@@ -1,11 +1,9 @@
-use std::collections::HashMap;
-use std::env;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{self, File};
 use std::io::{Read, Write};
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
-use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::path::{Component, Path, PathBuf};
 use std::str;
 
 use glob::Pattern;
@@ -13,11 +11,18 @@ use tempfile::{tempdir, TempDir};
 use tera::Context;
 use walkdir::WalkDir;
 
+use crate::answers::RecordedAnswers;
+use crate::archive::{self, stays_inside, ArchiveFormat};
+use crate::cache::Cache;
+use crate::config::Config;
 use crate::definition::{Hook, TemplateDefinition};
-use crate::errors::{map_io_err, new_error, ErrorKind, Result};
-use crate::utils::{
-    create_directory, get_source, is_binary, read_file, render_one_off_template, write_file, Source,
-};
+use crate::errors::{map_io_err, new_error, ErrorKind, GitError, Result};
+use crate::front_matter::FrontMatter;
+use crate::git;
+use crate::ignore::{IgnorePatterns, KICKSTART_IGNORE_FILENAME};
+use crate::output::Output;
+use crate::plan::{GenerationPlan, PlannedEntry};
+use crate::utils::{file_mode, get_source, is_binary, read_file, render_one_off_template, Source};
 use crate::{Value, Variable};
 
 /// Contains information about a given hook: what's the original path and what's the path
@@ -44,6 +49,38 @@ impl HookFile {
     }
 }
 
+/// Local paths are made absolute so they can still be found from another directory
+fn absolute_source(input: &str) -> String {
+    match Path::new(input).canonicalize() {
+        Ok(p) => format!("{}", p.display()),
+        Err(_) => input.to_string(),
+    }
+}
+
+/// How to find and load a template, see [Template::from_input_with_options]
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    /// The directory of the template to use, in the folder/repository/archive
+    pub directory: Option<String>,
+    /// The branch, tag or commit SHA to checkout for git templates
+    pub git_ref: Option<String>,
+    /// Where git templates are cloned
+    pub cache: Cache,
+    /// Used to resolve the template aliases
+    pub config: Config,
+}
+
+impl Default for LoadOptions {
+    fn default() -> LoadOptions {
+        LoadOptions {
+            directory: None,
+            git_ref: None,
+            cache: Cache::default_location(),
+            config: Config::default(),
+        }
+    }
+}
+
 /// The current template being generated.
 /// This is what you will end up interacting the most as a library.
 #[derive(Debug)]
@@ -54,39 +91,124 @@ pub struct Template {
     variables: HashMap<String, Value>,
     /// Local path to the template folder
     path: PathBuf,
+    /// Where the template was loaded from: a local path, an archive or a git URL
+    source: String,
+    /// The directory of the template in its source
+    directory: Option<String>,
+    /// The commit checked out if the template comes from a git repository
+    revision: Option<String>,
     /// Temp dir created to store the hooks after templating
     tmp_dir: TempDir,
+    /// Temp dir where the template was extracted if it comes from an archive
+    extracted: Option<TempDir>,
 }
 
 impl Template {
     /// Load a template from a string.
     /// It will try to detect whether this is a local folder or whether
     /// it should try to clone it.
+    /// Repositories are cloned in the default cache, see [Cache::default_location].
+    /// Use [Template::from_input_with_options] to checkout a specific branch, tag or commit.
     pub fn from_input(input: &str, directory: Option<&str>) -> Result<Template> {
-        match get_source(input) {
-            Source::Git(remote) => Template::from_git(&remote, directory),
+        let options =
+            LoadOptions { directory: directory.map(|d| d.to_string()), ..LoadOptions::default() };
+        Template::from_input_with_options(input, &options)
+    }
+
+    /// Same as [Template::from_input] but using the given cache for repositories and
+    /// checking out `git_ref` if there is one.
+    pub fn from_input_with_cache(
+        input: &str,
+        directory: Option<&str>,
+        git_ref: Option<&str>,
+        cache: &Cache,
+    ) -> Result<Template> {
+        let options = LoadOptions {
+            directory: directory.map(|d| d.to_string()),
+            git_ref: git_ref.map(|r| r.to_string()),
+            cache: cache.clone(),
+            ..LoadOptions::default()
+        };
+        Template::from_input_with_options(input, &options)
+    }
+
+    /// Same as [Template::from_input] but with more control on how the template is found.
+    /// If the input is an alias from the config, the ref and directory of the alias are used
+    /// unless they are set in the options.
+    pub fn from_input_with_options(input: &str, options: &LoadOptions) -> Result<Template> {
+        let (source, alias) = get_source(input, &options.config);
+        let directory =
+            options.directory.as_deref().or_else(|| alias.and_then(|a| a.directory.as_deref()));
+        let git_ref =
+            options.git_ref.as_deref().or_else(|| alias.and_then(|a| a.git_ref.as_deref()));
+        let cache = &options.cache;
+
+        match source {
+            Source::Git(remote) => {
+                Template::from_git_with_cache(&remote, directory, git_ref, cache)
+            }
+            Source::Local(path) if git_ref.is_some() => {
+                Template::from_git_with_cache(&path.to_string_lossy(), directory, git_ref, cache)
+            }
             Source::Local(path) => Template::from_local(&path, directory),
+            Source::Archive(path) if git_ref.is_some() => Err(new_error(ErrorKind::Archive {
+                path,
+                reason: "a branch, tag or commit can only be used with git templates".to_string(),
+            })),
+            Source::Archive(path) => Template::from_archive(&path, directory),
         }
     }
 
+    /// Load a template from a local `.tar.gz`, `.tgz` or `.zip` archive.
+    /// The archive is extracted in a temporary directory that lives as long as the template.
+    /// If the archive only contains a single directory, that directory is used as the root.
+    pub fn from_archive(path: &Path, directory: Option<&str>) -> Result<Template> {
+        let format = ArchiveFormat::from_path(path).ok_or_else(|| {
+            new_error(ErrorKind::Archive {
+                path: path.to_path_buf(),
+                reason: "only .tar.gz, .tgz and .zip files are supported".to_string(),
+            })
+        })?;
+        let extracted = tempdir()?;
+        let root = archive::extract(path, format, extracted.path())?;
+
+        let mut template = Template::from_local(&root, directory)?;
+        template.extracted = Some(extracted);
+        template.source = absolute_source(&path.to_string_lossy());
+        Ok(template)
+    }
+
     /// Load a template from git.
-    /// This will clone the repository if possible in the temporary directory of the user
+    /// This will clone the repository in the default cache, or update it if it was already
+    /// there, and use its default branch.
     pub fn from_git(remote: &str, directory: Option<&str>) -> Result<Template> {
-        // Clone the remote in git first in /tmp
-        let mut tmp = env::temp_dir();
-        tmp.push(remote.split('/').last().unwrap_or("kickstart"));
-        if tmp.exists() {
-            fs::remove_dir_all(&tmp)?;
-        }
+        Template::from_git_with_cache(remote, directory, None, &Cache::default_location())
+    }
 
-        // Use git command rather than git2 as it seems there are some issues building it
-        // on some platforms:
-        // https://www.reddit.com/r/rust/comments/92mbk5/kickstart_a_scaffolding_tool_to_get_new_projects/e3ahegw
-        Command::new("git")
-            .args(["clone", "--recurse-submodules", remote, &format!("{}", tmp.display())])
-            .output()
-            .map_err(|err| new_error(ErrorKind::Git { err }))?;
-        Template::from_local(&tmp, directory)
+    /// Same as [Template::from_git] but using the given cache and checking out `git_ref`
+    /// if there is one.
+    pub fn from_git_with_cache(
+        remote: &str,
+        directory: Option<&str>,
+        git_ref: Option<&str>,
+        cache: &Cache,
+    ) -> Result<Template> {
+        let repo = cache.fetch(remote, git_ref)?;
+        let revision = git::head_revision(&repo)?;
+
+        let mut template = Template::from_local(&repo, directory).map_err(|e| match e.kind {
+            ErrorKind::MissingTemplateDefinition => {
+                let err = GitError::MissingTemplateDefinition {
+                    remote: remote.to_string(),
+                    directory: directory.map(|d| d.to_string()),
+                };
+                new_error(ErrorKind::Git { err })
+            }
+            _ => e,
+        })?;
+        template.revision = Some(revision);
+        template.source = absolute_source(remote);
+        Ok(template)
     }
 
     /// Load a template from a local path
@@ -103,7 +225,98 @@ impl Template {
         let definition: TemplateDefinition = toml::from_str(&read_file(&conf_path)?)
             .map_err(|err| new_error(ErrorKind::Toml { err }))?;
 
-        Ok(Template { path: buf, definition, variables: HashMap::new(), tmp_dir: tempdir()? })
+        Ok(Template {
+            path: buf,
+            definition,
+            variables: HashMap::new(),
+            source: absolute_source(&path.to_string_lossy()),
+            directory: directory.map(|d| d.to_string()),
+            revision: None,
+            tmp_dir: tempdir()?,
+            extracted: None,
+        })
+    }
+
+    /// The full SHA of the commit the template was loaded from.
+    /// Only set for templates loaded from a git repository.
+    pub fn revision(&self) -> Option<&str> {
+        self.revision.as_deref()
+    }
+
+    /// Load a template from answers recorded during a previous generation and set
+    /// its variables, see [Template::write_answers] and [Template::set_variables_from_previous].
+    /// Templates from git repositories are loaded at the recorded revision.
+    pub fn from_answers(answers: &RecordedAnswers, cache: &Cache) -> Result<Template> {
+        let options = LoadOptions {
+            directory: answers.directory.clone(),
+            git_ref: answers.revision.clone(),
+            cache: cache.clone(),
+            ..LoadOptions::default()
+        };
+        let mut template = Template::from_input_with_options(&answers.source, &options)?;
+        template.set_variables_from_previous(&answers.variables)?;
+        Ok(template)
+    }
+
+    /// Sets the variables from the values of a previous generation, possibly with another
+    /// version of the template.
+    /// Variables that are new or whose previous value is not valid anymore, as well as secrets
+    /// which are never recorded, are read from their environment variable if they have one or
+    /// use their default.
+    pub fn set_variables_from_previous(
+        &mut self,
+        previous: &BTreeMap<String, Value>,
+    ) -> Result<()> {
+        self.set_variables_from_previous_with_env(previous, &|name| std::env::var(name).ok())
+    }
+
+    /// Same as [Template::set_variables_from_previous] but looking up the environment
+    /// variables with `env`
+    pub(crate) fn set_variables_from_previous_with_env(
+        &mut self,
+        previous: &BTreeMap<String, Value>,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<()> {
+        let mut vals = HashMap::new();
+        for var in &self.definition.variables {
+            if !self.should_ask_variable(&var.name, &vals)? {
+                continue;
+            }
+            let value = match previous.get(&var.name) {
+                Some(v) if var.check_value(v).is_ok() => var.coerce_value(v.clone()),
+                _ => match var.value_from(env)? {
+                    Some(v) => v,
+                    None => self.get_default_for(&var.name, &vals)?,
+                },
+            };
+            vals.insert(var.name.clone(), value);
+        }
+        self.set_variables(vals)
+    }
+
+    /// What should be recorded to be able to generate the project again.
+    /// Secret variables are left out.
+    pub fn recorded_answers(&self) -> RecordedAnswers {
+        let secrets: HashSet<_> =
+            self.definition.variables.iter().filter(|v| v.secret).map(|v| &v.name).collect();
+        RecordedAnswers {
+            source: self.source.clone(),
+            directory: self.directory.clone(),
+            revision: self.revision.clone(),
+            version: self.definition.version.clone(),
+            variables: self
+                .variables
+                .iter()
+                .filter(|(name, _)| !secrets.contains(name))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        }
+    }
+
+    /// Writes the answers file in the output directory and returns its path.
+    /// The project can then be generated again with [Template::from_answers].
+    pub fn write_answers(&self, output_dir: &Path) -> Result<PathBuf> {
+        self.recorded_answers().write(output_dir)
     }
 
     fn get_variable_by_name(&self, name: &str) -> Result<&Variable> {
@@ -123,6 +336,9 @@ impl Template {
         match &var.default {
             Value::Integer(i) => Ok(Value::Integer(*i)),
             Value::Boolean(i) => Ok(Value::Boolean(*i)),
+            Value::Float(i) => Ok(Value::Float(*i)),
+            Value::Version(i) => Ok(Value::Version(i.clone())),
+            Value::Array(i) => Ok(Value::Array(i.clone())),
             Value::String(i) => {
                 // TODO: Very inefficient but might be ok?
                 let mut context = Context::new();
@@ -135,10 +351,22 @@ impl Template {
         }
     }
 
-    /// Insert a single variable.
+    /// Checks that the value can be used for the given variable, see [Variable::check_value].
+    /// Will error if the template doesn't know that variable name.
+    pub fn check_value(&self, name: &str, value: &Value) -> Result<()> {
+        self.get_variable_by_name(name)?.check_value(value)
+    }
+
+    /// Parses the value of the given variable from a string, see [Variable::parse_value].
+    /// Will error if the template doesn't know that variable name.
+    pub fn parse_value(&self, name: &str, input: &str) -> Result<Value> {
+        self.get_variable_by_name(name)?.parse_value(input)
+    }
+
+    /// Insert a single variable, converted with [Variable::coerce_value].
     /// Will error if the template doesn't know that variable name.
     pub fn insert_variable(&mut self, name: &str, value: Value) -> Result<()> {
-        self.get_variable_by_name(name)?;
+        let value = self.get_variable_by_name(name)?.coerce_value(value);
         self.variables.insert(name.to_string(), value);
 
         Ok(())
@@ -164,13 +392,8 @@ impl Template {
 
         for hook in hooks {
             // First we check whether we need to run it or not
-            if let Some(cond) = &hook.only_if {
-                if let Some(val) = self.variables.get(&cond.name) {
-                    if *val != cond.value {
-                        continue;
-                    }
-                } else {
-                    // Not having it means we didn't even ask the question
+            if let Some(ref cond) = hook.only_if {
+                if !cond.is_met(&self.variables)? {
                     continue;
                 }
             }
@@ -215,44 +438,54 @@ impl Template {
     /// Will error if the template doesn't know that variable name.
     pub fn should_ask_variable(&self, name: &str, vals: &HashMap<String, Value>) -> Result<bool> {
         let var = self.get_variable_by_name(name)?;
-        if let Some(ref cond) = var.only_if {
-            if let Some(val) = vals.get(&cond.name) {
-                Ok(val == &cond.value)
-            } else {
-                // This means we never even asked the question
-                Ok(false)
-            }
-        } else {
-            Ok(true)
+        match var.only_if {
+            Some(ref cond) => cond.is_met(vals),
+            None => Ok(true),
         }
     }
 
-    /// Generate the template at the given output directory
-    pub fn generate(&self, output_dir: &Path) -> Result<()> {
+    /// Computes everything the generation would do in the given output directory, without
+    /// writing anything: the walk, the rendering, `ignore`, `include_if`, `exclude_if`,
+    /// `copy_without_render` and `cleanup` are all applied.
+    pub fn plan(&self, output_dir: &Path) -> Result<GenerationPlan> {
+        // The output directory might not exist yet, in which case it can't be in the template
+        self.plan_excluding(output_dir.canonicalize().ok())
+    }
+
+    /// Plans the generation, skipping `output_dir` if it is inside the template
+    fn plan_excluding(&self, output_dir: Option<PathBuf>) -> Result<GenerationPlan> {
         let mut context = Context::new();
         for (key, val) in &self.variables {
             context.insert(key, val);
         }
 
-        if !output_dir.exists() {
-            create_directory(output_dir)?;
-        }
-        let output_dir = output_dir.canonicalize()?;
-
         // Create the glob patterns of files to copy without rendering first, only once
-        let mut patterns = Vec::with_capacity(self.definition.copy_without_render.len());
-        for s in &self.definition.copy_without_render {
-            let rendered = render_one_off_template(s, &context, None)?;
-            match Pattern::new(&rendered) {
-                Ok(p) => patterns.push(p),
-                Err(err) => {
-                    return Err(new_error(ErrorKind::InvalidGlobPattern {
-                        err,
-                        pattern_before_rendering: s.clone(),
-                        pattern_after_rendering: if s == &rendered { None } else { Some(rendered) },
-                    }));
+        let patterns = self
+            .definition
+            .copy_without_render
+            .iter()
+            .map(|s| build_pattern(s, &context))
+            .collect::<Result<Vec<_>>>()?;
+        let permissions = self
+            .definition
+            .permissions
+            .iter()
+            .map(|p| Ok((build_pattern(&p.pattern, &context)?, p.mode)))
+            .collect::<Result<Vec<_>>>()?;
+        // The paths of `include_if` whose condition is false and of `exclude_if` whose
+        // condition is true
+        let mut excluded_patterns = Vec::new();
+        for (conditions, exclude_when) in
+            [(&self.definition.include_if, false), (&self.definition.exclude_if, true)]
+        {
+            for cond in conditions {
+                if cond.condition.is_met(&self.variables)? == exclude_when {
+                    for p in &cond.paths {
+                        // Directories are matched by their path, without a trailing `/`
+                        excluded_patterns.push(build_pattern(p.trim_end_matches('/'), &context)?);
+                    }
                 }
-            };
+            }
         }
 
         let start_path = if let Some(ref directory) = self.definition.directory {
@@ -261,53 +494,99 @@ impl Template {
             self.path.clone()
         };
 
-        // And now generate the files in the output dir given
+        let ignored = IgnorePatterns::load(
+            &self.path,
+            &self.definition.ignore,
+            self.definition.respect_gitignore,
+        )?;
+
+        // And now go through the files of the template
         let walker = WalkDir::new(&start_path)
-            .follow_links(self.definition.follow_symlinks)
+            .follow_links(self.definition.follow_symlinks && !self.definition.recreate_symlinks)
             .into_iter()
             .filter_entry(|e| {
                 // Ignore .git/ folder
                 let relative_path = e.path().strip_prefix(&start_path).expect("Stripping prefix");
                 if relative_path.starts_with(".git/")
                     || (relative_path.is_dir() && relative_path.starts_with(".git"))
-                    || e.path().canonicalize().expect("to canonicalize").starts_with(&output_dir)
                 {
                     return false;
                 }
+                // Skipping a directory here skips everything in it
+                let template_path = e.path().strip_prefix(&self.path).expect("Stripping prefix");
+                if ignored.is_ignored(template_path, e.file_type().is_dir())
+                    || e.file_name() == KICKSTART_IGNORE_FILENAME
+                {
+                    return false;
+                }
+                if let Some(ref output_dir) = output_dir {
+                    // Dangling symlinks can't be canonicalized but can't be the output either
+                    if e.path().canonicalize().is_ok_and(|p| p.starts_with(output_dir)) {
+                        return false;
+                    }
+                }
                 true
             })
             .filter_map(|e| e.ok());
 
         let hooks_paths = self.definition.all_hooks_paths();
+        let mut entries = Vec::new();
+        let mut excluded_dirs: Vec<PathBuf> = Vec::new();
+        // Front matter can write files in directories that are not in the template
+        let mut planned_dirs = HashSet::new();
 
-        'outer: for entry in walker {
+        for entry in walker {
             // Skip root folder and the template.toml
             if entry.path() == self.path || entry.path() == self.path.join("template.toml") {
                 continue;
             }
 
             let path = entry.path().strip_prefix(&self.path).unwrap();
-            if path.starts_with(&output_dir) {
-                continue;
-            }
             let path_str = format!("{}", path.display());
-            for ignored in &self.definition.ignore {
-                if ignored == &path_str || path_str.starts_with(ignored) {
-                    continue 'outer;
-                }
-            }
 
             // We automatically ignore hooks file
             if hooks_paths.contains(&path_str) {
-                continue 'outer;
+                continue;
             }
 
             let path_str = path_str.replace("$$", "|");
             let tpl = render_one_off_template(&path_str, &context, None)?;
-            let real_path = output_dir.join(Path::new(&tpl));
+            let mut relative_path = PathBuf::from(tpl);
+            let source = entry.path().to_path_buf();
+
+            // Excluded before reading anything so their content doesn't need to be valid
+            if excluded_dirs.iter().any(|d| relative_path.starts_with(d))
+                || excluded_patterns.iter().any(|p| p.matches_path(&relative_path))
+            {
+                if entry.file_type().is_dir() {
+                    excluded_dirs.push(relative_path);
+                }
+                continue;
+            }
+
+            if self.definition.recreate_symlinks && entry.path_is_symlink() {
+                let target = map_io_err(fs::read_link(entry.path()), entry.path())?;
+                let target = render_one_off_template(
+                    &target.to_string_lossy(),
+                    &context,
+                    Some(entry.path().to_path_buf()),
+                )?;
+                let target = PathBuf::from(target);
+                let parent = relative_path.parent().unwrap_or_else(|| Path::new(""));
+                if target.is_absolute() || !stays_inside(&parent.join(&target)) {
+                    return Err(new_error(ErrorKind::InvalidSymlink {
+                        path: relative_path,
+                        target,
+                    }));
+                }
+                entries.push(PlannedEntry::symlink(relative_path, source, target));
+                continue;
+            }
 
             if entry.path().is_dir() {
-                create_directory(&real_path)?;
+                if planned_dirs.insert(relative_path.clone()) {
+                    entries.push(PlannedEntry::directory(relative_path, source));
+                }
                 continue;
             }
 
@@ -316,60 +595,216 @@ impl Template {
             let mut buffer = Vec::new();
             f.read_to_end(&mut buffer)?;
 
-            // For patterns, we do not want the output directory to be included
-            let glob_real_path = real_path.strip_prefix(&output_dir).expect("valid path");
-            let no_render = patterns.iter().map(|p| p.matches_path(glob_real_path)).any(|x| x);
+            // Files that are binary or not valid UTF-8 have no front matter and are copied as is
+            let text = if is_binary(&buffer) { None } else { str::from_utf8(&buffer).ok() };
+            let (front_matter, body) = match text {
+                Some(text) => FrontMatter::extract(entry.path(), text)?,
+                None => (None, ""),
+            };
+            let front_matter = front_matter.unwrap_or_default();
+            if let Some(ref cond) = front_matter.skip_if {
+                let skipped = cond.is_met(&self.variables).map_err(|e| {
+                    new_error(ErrorKind::InvalidFrontMatter {
+                        path: source.clone(),
+                        reason: e.to_string(),
+                    })
+                })?;
+                if skipped {
+                    continue;
+                }
+            }
+            if let Some(ref path) = front_matter.path {
+                let rendered = render_one_off_template(path, &context, Some(source.clone()))?;
+                let Some(path) = normalize_relative_path(&rendered) else {
+                    return Err(new_error(ErrorKind::InvalidFrontMatter {
+                        path: source,
+                        reason: format!("`{}` is not a path inside the project", rendered),
+                    }));
+                };
+                // `include_if` and `exclude_if` also apply to the path the file is moved to,
+                // including the directories it will be in
+                if path.ancestors().any(|a| excluded_patterns.iter().any(|p| p.matches_path(a))) {
+                    continue;
+                }
+                let parent_source = source.parent().unwrap_or(&self.path);
+                let mut ancestors: Vec<_> = path.ancestors().skip(1).collect();
+                ancestors.reverse();
+                for dir in ancestors {
+                    if !dir.as_os_str().is_empty() && planned_dirs.insert(dir.to_path_buf()) {
+                        let source = parent_source.to_path_buf();
+                        entries.push(PlannedEntry::directory(dir.to_path_buf(), source));
+                    }
+                }
+                relative_path = path;
+            }
+
+            let no_render = front_matter.copy_without_render
+                || patterns.iter().any(|p| p.matches_path(&relative_path));
+            // The front matter wins, then the last matching permission, otherwise the file
+            // keeps its mode, which is the mode of the target for symlinks
+            let permission = permissions.iter().rev().find(|(p, _)| p.matches_path(&relative_path));
+            let mode = match (front_matter.mode, permission) {
+                (Some(mode), _) | (None, Some(&(_, mode))) => Some(mode),
+                (None, None) => file_mode(&map_io_err(fs::metadata(entry.path()), entry.path())?),
+            };
 
-            if no_render || is_binary(&buffer) {
-                map_io_err(fs::copy(entry.path(), &real_path), entry.path())?;
+            if text.is_none() {
+                let size = buffer.len() as u64;
+                entries.push(PlannedEntry::copied(relative_path, source, size, mode));
+                continue;
+            }
+            if no_render {
+                let copied = PlannedEntry::copied(relative_path, source, 0, mode);
+                entries.push(copied.with_contents(body.as_bytes().to_vec()));
                 continue;
             }
 
-            let contents = render_one_off_template(
-                str::from_utf8(&buffer).unwrap(),
-                &context,
-                Some(entry.path().to_path_buf()),
-            )?;
+            let contents =
+                render_one_off_template(body, &context, Some(entry.path().to_path_buf()))?;
 
-            write_file(&real_path, &contents)?;
+            entries.push(PlannedEntry::rendered(relative_path, source, contents, mode));
         }
 
+        let mut cleaned_up = Vec::new();
         for cleanup in &self.definition.cleanup {
-            if let Some(val) = self.variables.get(&cleanup.name) {
-                if *val == cleanup.value {
-                    for p in &cleanup.paths {
-                        let actual_path = render_one_off_template(p, &context, None)?;
-                        let path_to_delete = output_dir.join(actual_path).canonicalize()?;
-                        // Avoid path traversals
-                        if !path_to_delete.starts_with(&output_dir) || !path_to_delete.exists() {
-                            continue;
-                        }
-                        if path_to_delete.is_dir() {
-                            map_io_err(fs::remove_dir_all(&path_to_delete), &path_to_delete)?;
+            if cleanup.condition.is_met(&self.variables)? {
+                for p in &cleanup.paths {
+                    let actual_path = render_one_off_template(p, &context, None)?;
+                    // Avoid path traversals
+                    let Some(path_to_delete) = normalize_relative_path(&actual_path) else {
+                        continue;
+                    };
+                    entries.retain(|e| {
+                        if e.path.starts_with(&path_to_delete) {
+                            cleaned_up.push(e.path.clone());
+                            false
                         } else {
-                            map_io_err(fs::remove_file(&path_to_delete), &path_to_delete)?;
+                            true
                         }
-                    }
+                    });
                 }
             }
         }
 
-        Ok(())
+        Ok(GenerationPlan { entries, cleaned_up })
+    }
+
+    /// Generate the template at the given output directory
+    pub fn generate(&self, output_dir: &Path) -> Result<()> {
+        let plan = self.plan(output_dir)?;
+        plan.write(output_dir)
+    }
+
+    /// Generate the template through the given output, eg in memory or in an archive
+    pub fn generate_to(&self, output: &mut dyn Output) -> Result<()> {
+        self.plan_excluding(None)?.write_to(output)
+    }
+
+    /// Generate the template in a `.tar.gz`, `.tgz` or `.zip` archive, keeping the file modes
+    pub fn generate_archive(&self, path: &Path) -> Result<()> {
+        archive::create(&self.plan_excluding(None)?, path)
+    }
+}
+
+/// Renders the glob pattern with the context before building it
+fn build_pattern(pattern: &str, context: &Context) -> Result<Pattern> {
+    let rendered = render_one_off_template(pattern, context, None)?;
+    Pattern::new(&rendered).map_err(|err| {
+        new_error(ErrorKind::InvalidGlobPattern {
+            err,
+            pattern_before_rendering: pattern.to_string(),
+            pattern_after_rendering: if pattern == rendered { None } else { Some(rendered) },
+        })
+    })
+}
+
+/// Returns the path without `.` components, or `None` if it is absolute or goes up
+/// with `..`
+fn normalize_relative_path(path: &str) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(c) => normalized.push(c),
+            Component::CurDir => {}
+            _ => return None,
+        }
     }
+    Some(normalized)
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    use std::process::Command;
+
     use tempfile::tempdir;
 
     use super::*;
+    use crate::answers::ANSWERS_FILENAME;
+    use crate::{ConflictStrategy, EntryKind, MemoryOutput};
+
+    fn run_git(args: &[&str], cwd: &Path) -> String {
+        let output = Command::new("git")
+            .args(["-c", "user.name=kickstart", "-c", "user.email=kickstart@example.com"])
+            .args(args)
+            .current_dir(cwd)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    /// Writes a template in `dir` with the given files and loads it with its default values.
+    /// It has a `name` variable defaulting to `hello`, `fields` are added at the top of its
+    /// template.toml, before that variable.
+    pub(crate) fn template_with(dir: &Path, fields: &str, files: &[(&str, &str)]) -> Template {
+        fs::create_dir_all(dir).unwrap();
+        let definition = format!(
+            "name = \"Test\"\nkickstart_version = 1\n{fields}\n\n[[variables]]\nname = \"name\"\ndefault = \"hello\"\nprompt = \"Name?\"\n"
+        );
+        fs::write(dir.join("template.toml"), definition).unwrap();
+        for (path, content) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let mut tpl = Template::from_local(dir, None).unwrap();
+        tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
+        tpl
+    }
+
+    /// Creates a bare repository with a `v1` tag, a `next` branch and the default branch
+    /// which all have a different `hello.md` content
+    fn create_bare_repo(dir: &Path, name: &str) -> PathBuf {
+        let work = dir.join("work");
+        fs::create_dir(&work).unwrap();
+        run_git(&["init", "--quiet", "--initial-branch=main"], &work);
+        fs::write(
+            work.join("template.toml"),
+            "name = \"Test\"\nkickstart_version = 1\n\n[[variables]]\nname = \"greeting\"\ndefault = \"Hello\"\nprompt = \"Greeting?\"\n",
+        )
+        .unwrap();
+        fs::write(work.join("hello.md"), "{{ greeting }} from v1").unwrap();
+        run_git(&["add", "."], &work);
+        run_git(&["commit", "--quiet", "-m", "v1"], &work);
+        run_git(&["tag", "v1"], &work);
+        run_git(&["checkout", "--quiet", "-b", "next"], &work);
+        fs::write(work.join("hello.md"), "{{ greeting }} from next").unwrap();
+        run_git(&["commit", "--quiet", "-am", "next"], &work);
+        run_git(&["checkout", "--quiet", "main"], &work);
+        fs::write(work.join("hello.md"), "{{ greeting }} from main").unwrap();
+        run_git(&["commit", "--quiet", "-am", "main"], &work);
+
+        let bare = dir.join(name);
+        run_git(&["clone", "--quiet", "--bare", "work", name], dir);
+        bare
+    }
 
     #[test]
     fn can_generate_from_local_path() {
         let dir = tempdir().unwrap();
         let mut tpl = Template::from_input("examples/complex", None).unwrap();
         tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
-        let res = tpl.generate(&dir.path().to_path_buf());
+        let res = tpl.generate(dir.path());
 
         assert!(res.is_ok());
         assert!(!dir.path().join("some-project").join("template.toml").exists());
@@ -381,7 +816,7 @@ mod tests {
         let dir = tempdir().unwrap();
         let mut tpl = Template::from_input("examples/with-directory", None).unwrap();
         tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
-        let res = tpl.generate(&dir.path().to_path_buf());
+        let res = tpl.generate(dir.path());
         assert!(res.is_ok());
         assert!(dir.path().join("template_root").join("Howdy.py").exists());
     }
@@ -391,7 +826,7 @@ mod tests {
         let dir = tempdir().unwrap();
         let mut tpl = Template::from_input("./", Some("examples/complex")).unwrap();
         tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
-        let res = tpl.generate(&dir.path().to_path_buf());
+        let res = tpl.generate(dir.path());
         assert!(res.is_ok());
         assert!(!dir.path().join("some-project").join("template.toml").exists());
         assert!(dir.path().join("some-project").join("logo.png").exists());
@@ -400,10 +835,16 @@ mod tests {
     #[test]
     fn can_generate_from_remote_repo() {
         let dir = tempdir().unwrap();
-        let mut tpl =
-            Template::from_input("https://github.com/Keats/rust-cli-template", None).unwrap();
+        let cache = Cache::new(dir.path().join("cache"));
+        let mut tpl = Template::from_input_with_cache(
+            "https://github.com/Keats/rust-cli-template",
+            None,
+            None,
+            &cache,
+        )
+        .unwrap();
         tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
-        let res = tpl.generate(&dir.path().to_path_buf());
+        let res = tpl.generate(dir.path());
 
         assert!(res.is_ok());
         assert!(!dir.path().join("My-CLI").join("template.toml").exists());
@@ -413,11 +854,16 @@ mod tests {
     #[test]
     fn can_generate_from_remote_repo_with_directory() {
         let dir = tempdir().unwrap();
-        let mut tpl =
-            Template::from_input("https://github.com/Keats/kickstart", Some("examples/complex"))
-                .unwrap();
+        let cache = Cache::new(dir.path().join("cache"));
+        let mut tpl = Template::from_input_with_cache(
+            "https://github.com/Keats/kickstart",
+            Some("examples/complex"),
+            None,
+            &cache,
+        )
+        .unwrap();
         tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
-        let res = tpl.generate(&dir.path().to_path_buf());
+        let res = tpl.generate(dir.path());
 
         assert!(res.is_ok());
         assert!(!dir.path().join("some-project").join("template.toml").exists());
@@ -429,9 +875,605 @@ mod tests {
         let dir = tempdir().unwrap();
         let mut tpl = Template::from_input("examples/slugify", None).unwrap();
         tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
-        let res = tpl.generate(&dir.path().to_path_buf());
+        let res = tpl.generate(dir.path());
         assert!(res.is_ok());
         assert!(!dir.path().join("template.toml").exists());
         assert!(dir.path().join("hello.md").exists());
     }
+
+    #[test]
+    fn can_plan_without_writing() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("output");
+        let mut tpl = Template::from_input("examples/complex", None).unwrap();
+        tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
+        tpl.insert_variable("spa", Value::Boolean(true)).unwrap();
+        tpl.insert_variable("js_framework", Value::String("Vue".to_string())).unwrap();
+        let plan = tpl.plan(&output).unwrap();
+
+        assert!(!output.exists());
+        let find = |p: &str| plan.entries.iter().find(|e| e.path == Path::new(p));
+        assert_eq!(find("some-project").unwrap().kind, EntryKind::Directory);
+        let logo = find("some-project/logo.png").unwrap();
+        assert_eq!(logo.kind, EntryKind::Copied);
+        assert_eq!(logo.size, fs::metadata(&logo.source).unwrap().len());
+        assert_eq!(find("some-project/README.md").unwrap().kind, EntryKind::Rendered);
+        assert!(find("template.toml").is_none());
+        // Removed by the cleanup
+        assert!(find("some-project/templates").is_none());
+        assert!(plan.cleaned_up.contains(&PathBuf::from("some-project/templates/base.html")));
+
+        plan.write(&output).unwrap();
+        assert!(output.join("some-project").join("logo.png").exists());
+        assert!(!output.join("some-project").join("templates").exists());
+    }
+
+    #[test]
+    fn can_generate_in_memory() {
+        let mut tpl = Template::from_input("examples/complex", None).unwrap();
+        tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
+        let mut output = MemoryOutput::default();
+        tpl.generate_to(&mut output).unwrap();
+
+        assert!(output.directories.contains(Path::new("some-project/docs")));
+        let logo = output.files.get(Path::new("some-project/logo.png")).unwrap();
+        assert_eq!(logo, &fs::read("examples/complex/{{project_name}}/logo.png").unwrap());
+        let readme = output.files.get(Path::new("some-project/README.md")).unwrap();
+        assert!(str::from_utf8(readme).unwrap().starts_with("# Some-project"));
+    }
+
+    #[test]
+    fn can_generate_archives() {
+        let dir = tempdir().unwrap();
+        let mut tpl = Template::from_input("examples/complex", None).unwrap();
+        tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
+
+        for name in ["project.tar.gz", "project.zip"] {
+            let path = dir.path().join(name);
+            tpl.generate_archive(&path).unwrap();
+            let extracted = dir.path().join(format!("{}-extracted", name));
+            fs::create_dir(&extracted).unwrap();
+            let format = ArchiveFormat::from_path(&path).unwrap();
+            // The project is in a single `some-project` directory, which becomes the root
+            let root = archive::extract(&path, format, &extracted).unwrap();
+            assert_eq!(root, extracted.join("some-project"));
+            assert!(root.join("logo.png").exists());
+            assert!(root.join("docs").join("base.md").exists());
+        }
+
+        let res = tpl.generate_archive(&dir.path().join("project.rar"));
+        assert!(matches!(res.unwrap_err().kind, ErrorKind::UnsupportedArchiveFormat { .. }));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn can_set_file_modes() {
+        let dir = tempdir().unwrap();
+        let tpl_dir = dir.path().join("template");
+        let tpl = template_with(
+            &tpl_dir,
+            "permissions = [\n    { pattern = \"{{name}}/*.key\", mode = 0o600 },\n]",
+            &[("{{name}}/run.sh", "echo {{name}}"), ("{{name}}/private.key", "{{name}}")],
+        );
+        let script = tpl_dir.join("{{name}}").join("run.sh");
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let output = dir.path().join("output");
+        tpl.generate(&output).unwrap();
+
+        let mode = |p: &str| fs::metadata(output.join(p)).unwrap().permissions().mode() & 0o777;
+        assert_eq!(fs::read_to_string(output.join("hello/run.sh")).unwrap(), "echo hello");
+        assert_eq!(mode("hello/run.sh"), 0o755);
+        assert_eq!(mode("hello/private.key"), 0o600);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_files_keep_the_mode_of_their_target() {
+        let dir = tempdir().unwrap();
+        let tpl_dir = dir.path().join("template");
+        let tpl = template_with(&tpl_dir, "", &[("run.sh", "echo {{name}}")]);
+        fs::set_permissions(tpl_dir.join("run.sh"), fs::Permissions::from_mode(0o750)).unwrap();
+        std::os::unix::fs::symlink("run.sh", tpl_dir.join("link.sh")).unwrap();
+
+        let output = dir.path().join("output");
+        tpl.generate(&output).unwrap();
+        let link = output.join("link.sh");
+        assert!(!fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&link).unwrap(), "echo hello");
+        assert_eq!(fs::metadata(&link).unwrap().permissions().mode() & 0o777, 0o750);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn can_recreate_symlinks() {
+        let dir = tempdir().unwrap();
+        let tpl_dir = dir.path().join("template");
+        let tpl = template_with(
+            &tpl_dir,
+            "recreate_symlinks = true",
+            &[("{{name}}/{{name}}.md", "{{name}}")],
+        );
+        std::os::unix::fs::symlink("{{name}}.md", tpl_dir.join("{{name}}").join("README.md"))
+            .unwrap();
+
+        let output = dir.path().join("output");
+        tpl.generate(&output).unwrap();
+        let link = output.join("hello").join("README.md");
+        assert_eq!(fs::read_link(&link).unwrap(), PathBuf::from("hello.md"));
+        assert_eq!(fs::read_to_string(&link).unwrap(), "hello");
+
+        // Links going outside of the project are refused
+        std::os::unix::fs::symlink("../../secret", tpl_dir.join("{{name}}").join("secret"))
+            .unwrap();
+        let res = tpl.plan(&output);
+        assert!(matches!(res.unwrap_err().kind, ErrorKind::InvalidSymlink { .. }));
+    }
+
+    #[test]
+    fn can_include_and_exclude_paths() {
+        let dir = tempdir().unwrap();
+        let tpl_dir = dir.path().join("template");
+        let fields = r#"
+[[include_if]]
+name = "docker"
+value = true
+paths = ["docker"]
+
+[[exclude_if]]
+only_if = "database in ['none', 'sqlite']"
+paths = ["*.sql"]
+
+[[variables]]
+name = "docker"
+default = false
+prompt = "Docker?"
+
+[[variables]]
+name = "database"
+default = "none"
+prompt = "Database?"
+"#;
+        let mut tpl = template_with(
+            &tpl_dir,
+            fields,
+            &[
+                ("docker/Dockerfile", "FROM rust"),
+                // Would fail to render since `tables` is not a variable
+                ("schema.sql", "{% for t in tables %}{{ t }}{% endfor %}"),
+                ("README.md", "{{ database }}"),
+            ],
+        );
+        let plan = tpl.plan(&dir.path().join("output")).unwrap();
+        let paths: Vec<_> = plan.entries.iter().map(|e| e.path.clone()).collect();
+        assert_eq!(paths, vec![PathBuf::from("README.md")]);
+
+        tpl.insert_variable("docker", Value::Boolean(true)).unwrap();
+        let plan = tpl.plan(&dir.path().join("output")).unwrap();
+        let mut paths: Vec<_> = plan.entries.iter().map(|e| e.path.clone()).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("README.md"),
+                PathBuf::from("docker"),
+                PathBuf::from("docker/Dockerfile")
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_on_invalid_conditions() {
+        let dir = tempdir().unwrap();
+        let tpl = template_with(
+            &dir.path().join("template"),
+            "[[cleanup]]\nonly_if = \"name ==\"\npaths = [\"hello.md\"]",
+            &[("hello.md", "{{ name }}")],
+        );
+        let err = tpl.generate(&dir.path().join("output")).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::InvalidCondition { .. }));
+    }
+
+    #[test]
+    fn can_use_front_matter() {
+        let dir = tempdir().unwrap();
+        let tpl = template_with(
+            &dir.path().join("template"),
+            "",
+            &[
+                (
+                    "run.sh",
+                    "+++ kickstart\npath = \"bin/{{ name }}.sh\"\nmode = 0o755\n+++\necho {{ name }}\n",
+                ),
+                ("raw.html", "+++ kickstart\ncopy_without_render = true\n+++\n{{ not_a_variable }}\n"),
+                (
+                    "skipped.md",
+                    "+++ kickstart\nskip_if = { name = \"name\", value = \"hello\" }\n+++\n{{ nope }}\n",
+                ),
+            ],
+        );
+        let mut output = MemoryOutput::default();
+        tpl.generate_to(&mut output).unwrap();
+
+        assert_eq!(output.directories, [PathBuf::from("bin")].into_iter().collect());
+        let files: Vec<_> = output.files.keys().cloned().collect();
+        assert_eq!(files, vec![PathBuf::from("bin/hello.sh"), PathBuf::from("raw.html")]);
+        assert_eq!(output.files[Path::new("bin/hello.sh")], b"echo hello\n");
+        assert_eq!(output.files[Path::new("raw.html")], b"{{ not_a_variable }}\n");
+        let plan = tpl.plan(&dir.path().join("output")).unwrap();
+        let script = plan.entries.iter().find(|e| e.path == Path::new("bin/hello.sh")).unwrap();
+        assert_eq!(script.mode, Some(0o755));
+    }
+
+    #[test]
+    fn front_matter_paths_are_excluded_but_not_ignored() {
+        let dir = tempdir().unwrap();
+        let fields = r#"ignore = ["ignored"]
+
+[[exclude_if]]
+name = "name"
+value = "hello"
+paths = ["private"]
+"#;
+        let tpl = template_with(
+            &dir.path().join("template"),
+            fields,
+            &[
+                ("notes.md", "+++ kickstart\npath = \"private/notes.md\"\n+++\n{{ name }}\n"),
+                ("kept.md", "+++ kickstart\npath = \"ignored/kept.md\"\n+++\n{{ name }}\n"),
+            ],
+        );
+        let plan = tpl.plan(&dir.path().join("output")).unwrap();
+        let paths: Vec<_> = plan.entries.iter().map(|e| e.path.clone()).collect();
+        assert_eq!(paths, vec![PathBuf::from("ignored"), PathBuf::from("ignored/kept.md")]);
+    }
+
+    #[test]
+    fn can_copy_files_that_are_not_utf8() {
+        let dir = tempdir().unwrap();
+        let tpl_dir = dir.path().join("template");
+        let tpl = template_with(&tpl_dir, "copy_without_render = [\"latin1.txt\"]", &[]);
+        // `café` in latin-1, which has no NUL bytes but isn't valid UTF-8
+        fs::write(tpl_dir.join("latin1.txt"), b"caf\xe9 {{ name }}\n").unwrap();
+
+        let mut output = MemoryOutput::default();
+        tpl.generate_to(&mut output).unwrap();
+
+        assert_eq!(output.files[Path::new("latin1.txt")], b"caf\xe9 {{ name }}\n");
+    }
+
+    #[test]
+    fn can_generate_from_git_ref() {
+        let dir = tempdir().unwrap();
+        let bare = create_bare_repo(dir.path(), "kickstart-test-refs.git");
+        let bare_str = bare.to_string_lossy();
+        let v1_sha = run_git(&["rev-parse", "v1"], &bare);
+        let cache = Cache::new(dir.path().join("cache"));
+
+        for (git_ref, expected) in [
+            (None, "Hello from main"),
+            (Some("v1"), "Hello from v1"),
+            (Some("next"), "Hello from next"),
+            (Some(v1_sha.as_str()), "Hello from v1"),
+        ] {
+            let out = tempdir().unwrap();
+            let mut tpl = Template::from_git_with_cache(&bare_str, None, git_ref, &cache).unwrap();
+            tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
+            tpl.generate(out.path()).unwrap();
+            assert_eq!(fs::read_to_string(out.path().join("hello.md")).unwrap(), expected);
+            let expected_revision = run_git(&["rev-parse", git_ref.unwrap_or("main")], &bare);
+            assert_eq!(tpl.revision(), Some(expected_revision.as_str()));
+        }
+    }
+
+    #[test]
+    fn errors_on_unknown_git_ref() {
+        let dir = tempdir().unwrap();
+        let bare = create_bare_repo(dir.path(), "kickstart-test-unknown-ref.git");
+        let options = LoadOptions {
+            git_ref: Some("nope".to_string()),
+            cache: Cache::new(dir.path().join("cache")),
+            ..LoadOptions::default()
+        };
+        let res = Template::from_input_with_options(&bare.to_string_lossy(), &options);
+        match res.unwrap_err().kind {
+            ErrorKind::Git { err: GitError::UnknownRef(r) } => assert_eq!(r, "nope"),
+            e => panic!("Unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn errors_on_failed_clone() {
+        let dir = tempdir().unwrap();
+        let cache = Cache::new(dir.path().join("cache"));
+        let missing = dir.path().join("missing.git");
+        let res = Template::from_git_with_cache(&missing.to_string_lossy(), None, None, &cache);
+        match res.unwrap_err().kind {
+            ErrorKind::Git { err: GitError::CommandFailed { status, stderr, .. } } => {
+                assert_ne!(status, Some(0));
+                assert!(!stderr.is_empty());
+            }
+            e => panic!("Unexpected error {:?}", e),
+        }
+        // Nothing is left in the cache
+        assert!(cache.entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn errors_on_repository_without_template() {
+        let dir = tempdir().unwrap();
+        let bare = create_bare_repo(dir.path(), "kickstart-test-no-template.git");
+        let cache = Cache::new(dir.path().join("cache"));
+        let res =
+            Template::from_git_with_cache(&bare.to_string_lossy(), Some("nested"), None, &cache);
+        match res.unwrap_err().kind {
+            ErrorKind::Git { err: GitError::MissingTemplateDefinition { directory, .. } } => {
+                assert_eq!(directory.as_deref(), Some("nested"));
+            }
+            e => panic!("Unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn local_repository_is_cloned_when_given_a_ref() {
+        let dir = tempdir().unwrap();
+        let bare = create_bare_repo(dir.path(), "kickstart-test-local-ref.git");
+        let options = LoadOptions {
+            git_ref: Some("next".to_string()),
+            cache: Cache::new(dir.path().join("cache")),
+            ..LoadOptions::default()
+        };
+        let tpl = Template::from_input_with_options(&bare.to_string_lossy(), &options).unwrap();
+        let expected_revision = run_git(&["rev-parse", "next"], &bare);
+        assert_eq!(tpl.revision(), Some(expected_revision.as_str()));
+    }
+
+    #[test]
+    fn cached_templates_are_updated_and_usable_offline() {
+        let dir = tempdir().unwrap();
+        let bare = create_bare_repo(dir.path(), "kickstart-test-cache.git");
+        let bare_str = bare.to_string_lossy();
+        let cache = Cache::new(dir.path().join("cache"));
+
+        let tpl = Template::from_git_with_cache(&bare_str, None, None, &cache).unwrap();
+        let first_revision = tpl.revision().unwrap().to_string();
+        assert_eq!(cache.entries().unwrap().len(), 1);
+        // The temporary clone was moved in the entry
+        assert_eq!(fs::read_dir(cache.root()).unwrap().count(), 1);
+        // `HEAD` is the default branch and uses the same entry
+        let tpl = Template::from_git_with_cache(&bare_str, None, Some("HEAD"), &cache).unwrap();
+        assert_eq!(tpl.revision(), Some(first_revision.as_str()));
+        assert_eq!(cache.entries().unwrap().len(), 1);
+
+        // A new commit upstream is picked up on the next load
+        let work = dir.path().join("work");
+        fs::write(work.join("hello.md"), "{{ greeting }} from main again").unwrap();
+        run_git(&["commit", "--quiet", "-am", "again"], &work);
+        run_git(&["push", "--quiet", &bare_str, "main"], &work);
+        let tpl = Template::from_git_with_cache(&bare_str, None, None, &cache).unwrap();
+        let new_revision = run_git(&["rev-parse", "main"], &bare);
+        assert_ne!(tpl.revision(), Some(first_revision.as_str()));
+        assert_eq!(tpl.revision(), Some(new_revision.as_str()));
+
+        // And it keeps working without the remote when offline
+        fs::remove_dir_all(&bare).unwrap();
+        let offline = cache.clone().offline(true);
+        let tpl = Template::from_git_with_cache(&bare_str, None, None, &offline).unwrap();
+        assert_eq!(tpl.revision(), Some(new_revision.as_str()));
+        assert!(Template::from_git_with_cache(&bare_str, None, Some("v1"), &offline).is_err());
+    }
+
+    /// Archives `examples/super-basic` in a `super-basic/` top-level directory
+    fn create_archives(dir: &Path) -> (PathBuf, PathBuf) {
+        let tar_path = dir.join("super-basic.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(
+            File::create(&tar_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut tar = tar::Builder::new(encoder);
+        tar.append_dir_all("super-basic", "examples/super-basic").unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+
+        let zip_path = dir.join("super-basic.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        for entry in WalkDir::new("examples/super-basic").into_iter().filter_map(|e| e.ok()) {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let name = Path::new("super-basic")
+                .join(entry.path().strip_prefix("examples/super-basic").unwrap());
+            zip.start_file(name.to_string_lossy(), zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(&fs::read(entry.path()).unwrap()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        (tar_path, zip_path)
+    }
+
+    #[test]
+    fn can_generate_from_archives() {
+        let dir = tempdir().unwrap();
+        let (tar_path, zip_path) = create_archives(dir.path());
+
+        for input in
+            [tar_path.to_string_lossy().to_string(), format!("file://{}", zip_path.display())]
+        {
+            let out = tempdir().unwrap();
+            let mut tpl = Template::from_input(&input, None).unwrap();
+            tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
+            tpl.generate(out.path()).unwrap();
+            assert!(out.path().join("Hello").join("Howdy.py").exists());
+            assert!(!out.path().join("template.toml").exists());
+        }
+    }
+
+    #[test]
+    fn can_generate_from_archive_with_directory() {
+        let dir = tempdir().unwrap();
+        let tar_path = dir.path().join("examples.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(
+            File::create(&tar_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut tar = tar::Builder::new(encoder);
+        tar.append_dir_all("examples", "examples").unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+
+        let out = tempdir().unwrap();
+        let mut tpl = Template::from_archive(&tar_path, Some("complex")).unwrap();
+        tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
+        tpl.generate(out.path()).unwrap();
+        assert!(out.path().join("some-project").join("logo.png").exists());
+    }
+
+    #[test]
+    fn refuses_archives_with_path_traversal() {
+        let dir = tempdir().unwrap();
+        let zip_path = dir.path().join("evil.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        zip.start_file("../evil.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"evil").unwrap();
+        zip.finish().unwrap();
+
+        let dest = dir.path().join("extracted");
+        fs::create_dir(&dest).unwrap();
+        let res = archive::extract(&zip_path, ArchiveFormat::Zip, &dest);
+        assert!(matches!(res.unwrap_err().kind, ErrorKind::Archive { .. }));
+        assert!(!dir.path().join("evil.txt").exists());
+        assert_eq!(fs::read_dir(&dest).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn refuses_git_refs_for_archives() {
+        let dir = tempdir().unwrap();
+        let (tar_path, _) = create_archives(dir.path());
+        let options = LoadOptions { git_ref: Some("v1".to_string()), ..LoadOptions::default() };
+        let res = Template::from_input_with_options(&tar_path.to_string_lossy(), &options);
+        assert!(matches!(res.unwrap_err().kind, ErrorKind::Archive { .. }));
+    }
+
+    #[test]
+    fn can_generate_from_alias() {
+        let dir = tempdir().unwrap();
+        let config: Config = toml::from_str(
+            r#"
+            [templates.complex]
+            url = "./"
+            directory = "examples/complex"
+            "#,
+        )
+        .unwrap();
+        let options = LoadOptions { config, ..LoadOptions::default() };
+        let mut tpl = Template::from_input_with_options("complex", &options).unwrap();
+        tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
+        tpl.generate(dir.path()).unwrap();
+        assert!(dir.path().join("some-project").join("logo.png").exists());
+
+        // The options win over the alias
+        let options =
+            LoadOptions { directory: Some("examples/super-basic".to_string()), ..options };
+        let tpl = Template::from_input_with_options("complex", &options).unwrap();
+        assert_eq!(tpl.definition.name, "Super basic");
+    }
+
+    #[test]
+    fn secrets_are_not_recorded() {
+        let dir = tempdir().unwrap();
+        let tpl_dir = dir.path().join("template");
+        let fields = r#"
+[[variables]]
+name = "api_token"
+default = ""
+prompt = "API token?"
+secret = true
+env = "KICKSTART_TEST_SECRETS_API_TOKEN"
+"#;
+        let mut tpl = template_with(&tpl_dir, fields, &[(".env", "TOKEN={{ api_token }}")]);
+        tpl.insert_variable("name", Value::String("world".to_string())).unwrap();
+        tpl.insert_variable("api_token", Value::String("s3cr3t".to_string())).unwrap();
+        let answers = tpl.recorded_answers();
+        assert_eq!(answers.variables.len(), 1);
+        assert!(!toml::to_string(&answers).unwrap().contains("s3cr3t"));
+
+        // Secrets come from their environment variable when they are not recorded
+        let env = |name: &str| {
+            (name == "KICKSTART_TEST_SECRETS_API_TOKEN").then(|| "from-env".to_string())
+        };
+        let mut replayed = Template::from_local(&tpl_dir, None).unwrap();
+        replayed.set_variables_from_previous_with_env(&answers.variables, &env).unwrap();
+        let mut output = MemoryOutput::default();
+        replayed.generate_to(&mut output).unwrap();
+        assert_eq!(output.files[Path::new(".env")], b"TOKEN=from-env");
+    }
+
+    #[test]
+    fn can_replay_from_recorded_answers() {
+        let dir = tempdir().unwrap();
+        let bare = create_bare_repo(dir.path(), "kickstart-test-replay.git");
+        let cache = Cache::new(dir.path().join("cache"));
+        let mut tpl =
+            Template::from_git_with_cache(&bare.to_string_lossy(), None, Some("v1"), &cache)
+                .unwrap();
+        tpl.insert_variable("greeting", Value::String("Bonjour".to_string())).unwrap();
+        let out = dir.path().join("out");
+        tpl.generate(&out).unwrap();
+        let answers_path = tpl.write_answers(&out).unwrap();
+        assert_eq!(answers_path, out.join(ANSWERS_FILENAME));
+
+        // The recorded revision is used, not the default branch
+        let answers = RecordedAnswers::from_file(&answers_path).unwrap();
+        assert_eq!(answers.revision.as_deref(), tpl.revision());
+        let replayed = Template::from_answers(&answers, &cache).unwrap();
+        let replay_out = dir.path().join("replay");
+        replayed.generate(&replay_out).unwrap();
+        assert_eq!(fs::read_to_string(replay_out.join("hello.md")).unwrap(), "Bonjour from v1");
+    }
+
+    #[test]
+    fn can_replay_in_the_generated_directory() {
+        let dir = tempdir().unwrap();
+        let mut tpl = Template::from_input("examples/super-basic", None).unwrap();
+        tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
+        tpl.generate(dir.path()).unwrap();
+        tpl.write_answers(dir.path()).unwrap();
+
+        let answers = RecordedAnswers::from_file(&dir.path().join(ANSWERS_FILENAME)).unwrap();
+        let cache = Cache::new(dir.path().join("cache"));
+        let replayed = Template::from_answers(&answers, &cache).unwrap();
+        let mut plan = replayed.plan(dir.path()).unwrap();
+        assert!(plan.resolve_conflicts(dir.path(), ConflictStrategy::Fail).unwrap().is_empty());
+        plan.write(dir.path()).unwrap();
+
+        // Only the files changed since then are conflicts
+        let changed = plan.entries.iter().find(|e| e.kind == EntryKind::Rendered).unwrap();
+        fs::write(dir.path().join(&changed.path), "changed").unwrap();
+        let plan = replayed.plan(dir.path()).unwrap();
+        assert_eq!(plan.conflicts(dir.path()), vec![changed.path.clone()]);
+    }
+
+    #[test]
+    fn can_record_answers_of_local_templates() {
+        let dir = tempdir().unwrap();
+        let mut tpl = Template::from_input("./", Some("examples/complex")).unwrap();
+        tpl.set_variables(tpl.definition.default_values().unwrap()).unwrap();
+        tpl.generate(dir.path()).unwrap();
+        tpl.write_answers(dir.path()).unwrap();
+
+        let answers = RecordedAnswers::from_file(&dir.path().join(ANSWERS_FILENAME)).unwrap();
+        assert_eq!(answers.revision, None);
+        assert_eq!(answers.directory.as_deref(), Some("examples/complex"));
+        assert!(Path::new(&answers.source).is_absolute());
+        assert_eq!(answers.variables.get("database"), Some(&Value::String("postgres".into())));
+
+        let cache = Cache::new(dir.path().join("cache"));
+        let replayed = Template::from_answers(&answers, &cache).unwrap();
+        assert_eq!(replayed.recorded_answers(), answers);
+    }
+
+    #[test]
+    fn local_templates_have_no_revision() {
+        let tpl = Template::from_input("examples/super-basic", None).unwrap();
+        assert_eq!(tpl.revision(), None);
+    }
 }
@@ -0,0 +1,98 @@
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::errors::{new_error, Error, ErrorKind, GitError, Result};
+
+fn spawn_error(err: io::Error) -> Error {
+    let err = if err.kind() == io::ErrorKind::NotFound {
+        GitError::NotInstalled
+    } else {
+        GitError::Io(err)
+    };
+    new_error(ErrorKind::Git { err })
+}
+
+/// Runs a git command and returns its trimmed stdout.
+/// We use the git command rather than git2 as it seems there are some issues building it
+/// on some platforms:
+/// https://www.reddit.com/r/rust/comments/92mbk5/kickstart_a_scaffolding_tool_to_get_new_projects/e3ahegw
+fn run(args: &[&str], cwd: Option<&Path>) -> Result<String> {
+    let mut command = Command::new("git");
+    command.args(args);
+    if let Some(dir) = cwd {
+        command.current_dir(dir);
+    }
+    let output = command.output().map_err(spawn_error)?;
+
+    if !output.status.success() {
+        let err = GitError::CommandFailed {
+            command: format!("git {}", args.join(" ")),
+            status: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        };
+        return Err(new_error(ErrorKind::Git { err }));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Clones the remote, including its submodules, in the given directory
+pub(crate) fn clone(remote: &str, dest: &Path) -> Result<()> {
+    run(&["clone", "--recurse-submodules", remote, &format!("{}", dest.display())], None)?;
+    Ok(())
+}
+
+/// Fetches the latest branches and tags of the `origin` remote
+pub(crate) fn fetch(repo: &Path) -> Result<()> {
+    run(&["fetch", "--quiet", "--tags", "--force", "--prune", "origin"], Some(repo))?;
+    Ok(())
+}
+
+/// Finds the commit a branch, tag or commit SHA points to.
+/// Remote branches are tried first so we always get the latest commit of a branch rather
+/// than whatever the local one was pointing to.
+fn resolve_ref(repo: &Path, git_ref: &str) -> Result<String> {
+    let remote_ref = format!("origin/{git_ref}^{{commit}}");
+    if let Ok(sha) = run(&["rev-parse", "--verify", "--quiet", &remote_ref], Some(repo)) {
+        return Ok(sha);
+    }
+    run(&["rev-parse", "--verify", "--quiet", &format!("{git_ref}^{{commit}}")], Some(repo))
+        .map_err(|_| new_error(ErrorKind::Git { err: GitError::UnknownRef(git_ref.to_string()) }))
+}
+
+/// Checks out the given branch, tag or commit SHA and updates the submodules accordingly
+pub(crate) fn checkout(repo: &Path, git_ref: &str) -> Result<()> {
+    let sha = resolve_ref(repo, git_ref)?;
+    run(&["checkout", "--quiet", "--force", "--detach", &sha], Some(repo))?;
+    run(&["submodule", "update", "--init", "--recursive"], Some(repo))?;
+    Ok(())
+}
+
+/// Three-way merge of a file: the changes from `base` to `other` are applied to `current`.
+/// Returns the merged content and whether there were conflicts, which are marked in the content.
+pub(crate) fn merge_file(current: &Path, base: &Path, other: &Path) -> Result<(Vec<u8>, bool)> {
+    let paths = [current, base, other].map(|p| format!("{}", p.display()));
+    let mut args =
+        vec!["merge-file", "-p", "-L", "project", "-L", "old template", "-L", "new template"];
+    args.extend(paths.iter().map(|p| p.as_str()));
+    let output = Command::new("git").args(&args).output().map_err(spawn_error)?;
+
+    // The exit code is the number of conflicts, or negative on errors
+    match output.status.code() {
+        Some(code) if (0..128).contains(&code) => Ok((output.stdout, code > 0)),
+        status => {
+            let err = GitError::CommandFailed {
+                command: format!("git {}", args.join(" ")),
+                status,
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            };
+            Err(new_error(ErrorKind::Git { err }))
+        }
+    }
+}
+
+/// Returns the full SHA of the commit currently checked out
+pub(crate) fn head_revision(repo: &Path) -> Result<String> {
+    run(&["rev-parse", "HEAD"], Some(repo))
+}
@@ -0,0 +1,228 @@
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+use walkdir::WalkDir;
+
+use crate::errors::{new_error, ErrorKind, Result};
+use crate::utils::read_file;
+
+/// The name of the files listing paths to ignore in a template, with the `.gitignore` syntax
+pub(crate) const KICKSTART_IGNORE_FILENAME: &str = ".kickstartignore";
+
+/// Like in `.gitignore` files, `*` doesn't match `/` in ignore files
+const IGNORE_FILE_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: Pattern,
+    /// `!pattern`: the paths matching it are not ignored anymore
+    negated: bool,
+    /// Patterns ending with `/` in ignore files only match directories
+    only_dirs: bool,
+    options: MatchOptions,
+}
+
+impl Rule {
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        (is_dir || !self.only_dirs) && self.pattern.matches_path_with(path, self.options)
+    }
+}
+
+/// Glob patterns of paths to skip, where a pattern starting with `!` re-includes what an earlier
+/// pattern ignored. The last matching pattern wins.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct IgnorePatterns {
+    rules: Vec<Rule>,
+}
+
+/// Splits the `!` of negated patterns. A trailing `/` is removed since directories are matched
+/// by their path like files.
+fn parse_pattern(pattern: &str) -> (&str, bool) {
+    let (pattern, negated) = match pattern.strip_prefix('!') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+    (pattern.trim_end_matches('/'), negated)
+}
+
+impl IgnorePatterns {
+    pub(crate) fn new(patterns: &[String]) -> Result<IgnorePatterns> {
+        let mut rules = Vec::with_capacity(patterns.len());
+        for raw in patterns {
+            let (pattern, negated) = parse_pattern(raw);
+            let pattern = Pattern::new(pattern).map_err(|err| {
+                new_error(ErrorKind::InvalidGlobPattern {
+                    err,
+                    pattern_before_rendering: raw.clone(),
+                    pattern_after_rendering: None,
+                })
+            })?;
+            rules.push(Rule { pattern, negated, only_dirs: false, options: MatchOptions::new() });
+        }
+        Ok(IgnorePatterns { rules })
+    }
+
+    /// Loads the patterns of `ignore` in template.toml along with the ones of all the
+    /// `.kickstartignore` files of the template, and of the `.gitignore` files if
+    /// `respect_gitignore` is set. Patterns in `ignore` come last, so they take precedence.
+    pub(crate) fn load(
+        template_root: &Path,
+        ignore: &[String],
+        respect_gitignore: bool,
+    ) -> Result<IgnorePatterns> {
+        let mut files: Vec<PathBuf> = WalkDir::new(template_root)
+            .into_iter()
+            .filter_entry(|e| e.file_name() != ".git")
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                e.file_type().is_file()
+                    && (e.file_name() == KICKSTART_IGNORE_FILENAME
+                        || (respect_gitignore && e.file_name() == ".gitignore"))
+            })
+            .map(|e| e.path().strip_prefix(template_root).expect("valid path").to_path_buf())
+            .collect();
+        // Files in subdirectories override their parents, and `.kickstartignore` overrides
+        // the `.gitignore` of the same directory
+        files.sort_by_key(|p| {
+            (p.components().count(), p.file_name().is_some_and(|n| n == KICKSTART_IGNORE_FILENAME))
+        });
+
+        let mut patterns = IgnorePatterns::default();
+        for file in files {
+            let dir = file.parent().unwrap_or_else(|| Path::new(""));
+            patterns.add_ignore_file(&read_file(&template_root.join(&file))?, dir);
+        }
+        patterns.rules.extend(IgnorePatterns::new(ignore)?.rules);
+        Ok(patterns)
+    }
+
+    /// Adds the patterns of an ignore file in the `dir` directory of the template,
+    /// following the `.gitignore` rules. Invalid patterns are skipped, like git does.
+    fn add_ignore_file(&mut self, content: &str, dir: &Path) {
+        let dir = Pattern::escape(&dir.to_string_lossy());
+        for line in content.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (line, negated) = match line.strip_prefix('!') {
+                Some(l) => (l, true),
+                None => (line.strip_prefix('\\').unwrap_or(line), false),
+            };
+            let only_dirs = line.ends_with('/');
+            let line = line.trim_end_matches('/');
+            // Patterns with a `/` are relative to the directory of the file, the other ones
+            // match at any depth
+            let pattern = match line.strip_prefix('/') {
+                Some(anchored) => anchored.to_string(),
+                None if line.contains('/') => line.to_string(),
+                None => format!("**/{}", line),
+            };
+            let pattern = if dir.is_empty() { pattern } else { format!("{}/{}", dir, pattern) };
+            if let Ok(pattern) = Pattern::new(&pattern) {
+                self.rules.push(Rule { pattern, negated, only_dirs, options: IGNORE_FILE_OPTIONS });
+            }
+        }
+    }
+
+    /// Whether that path, relative to the template, is ignored.
+    /// Everything in an ignored directory should be ignored as well, which is up to the caller.
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.rules.iter().rev().find(|r| r.matches(path, is_dir)).is_some_and(|r| !r.negated)
+    }
+
+    /// Returns an error message for every invalid pattern
+    pub(crate) fn validate(patterns: &[String]) -> Vec<String> {
+        patterns
+            .iter()
+            .filter_map(|raw| {
+                let (pattern, _) = parse_pattern(raw);
+                Pattern::new(pattern)
+                    .err()
+                    .map(|e| format!("In ignore, `{raw}` is not a valid pattern: {e}"))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_ignore_with_globs() {
+        let patterns = IgnorePatterns::new(&[
+            "*.pyc".to_string(),
+            "**/node_modules".to_string(),
+            "docs/".to_string(),
+            "*.log".to_string(),
+            "!keep.log".to_string(),
+        ])
+        .unwrap();
+
+        let inputs = vec![
+            ("main.pyc", true),
+            ("src/lib/main.pyc", true),
+            ("main.py", false),
+            ("node_modules", true),
+            ("web/node_modules", true),
+            ("docs", true),
+            ("documentation", false),
+            ("debug.log", true),
+            ("keep.log", false),
+        ];
+        for (path, expected) in inputs {
+            assert_eq!(patterns.is_ignored(Path::new(path), false), expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn errors_on_invalid_patterns() {
+        let patterns = vec!["*.pyc".to_string(), "![".to_string()];
+        assert!(IgnorePatterns::new(&patterns).is_err());
+        assert_eq!(IgnorePatterns::validate(&patterns).len(), 1);
+    }
+
+    #[test]
+    fn can_use_ignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("sub").join("nested")).unwrap();
+        std::fs::write(
+            root.join(KICKSTART_IGNORE_FILENAME),
+            "# Comment\n*.log\n/target\nbuild/\nsub/*.tmp\n\\#notacomment\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("sub").join(KICKSTART_IGNORE_FILENAME), "!keep.log\n").unwrap();
+        std::fs::write(root.join(".gitignore"), "*.secret\n").unwrap();
+
+        let patterns = IgnorePatterns::load(root, &["*.md".to_string()], false).unwrap();
+        let inputs = vec![
+            ("debug.log", false, true),
+            ("sub/nested/debug.log", false, true),
+            ("sub/keep.log", false, false),
+            ("sub/nested/keep.log", false, false),
+            ("keep.log", false, true),
+            ("target", true, true),
+            ("sub/target", true, false),
+            ("build", true, true),
+            ("sub/build", true, true),
+            ("build", false, false),
+            ("sub/a.tmp", false, true),
+            ("sub/nested/a.tmp", false, false),
+            ("#notacomment", false, true),
+            ("README.md", false, true),
+            ("a.secret", false, false),
+        ];
+        for (path, is_dir, expected) in inputs {
+            assert_eq!(patterns.is_ignored(Path::new(path), is_dir), expected, "{}", path);
+        }
+
+        let patterns = IgnorePatterns::load(root, &[], true).unwrap();
+        assert!(patterns.is_ignored(Path::new("a.secret"), false));
+    }
+}
@@ -4,15 +4,36 @@
 //! See the [kickstart binary](https://github.com/Keats/kickstart/blob/master/src/main.rs)
 //! for an example on how to use the library.
 
+mod answers;
+mod archive;
+mod cache;
 #[cfg(feature = "cli")]
 pub mod cli;
+mod config;
 mod definition;
 pub mod errors;
+mod expression;
 mod filters;
+mod front_matter;
 mod generation;
+mod git;
+mod ignore;
+mod output;
+mod plan;
+mod update;
 mod utils;
 mod value;
 
-pub use definition::{Cleanup, Condition, Hook, TemplateDefinition, Variable};
-pub use generation::{HookFile, Template};
+pub use answers::{read_answers_file, RecordedAnswers, ANSWERS_FILENAME};
+pub use cache::{Cache, CacheEntry};
+pub use config::{Config, TemplateAlias};
+pub use definition::{
+    Cleanup, Condition, ConditionalPaths, Hook, Permission, TemplateDefinition, Variable,
+    VariableType,
+};
+pub use front_matter::FrontMatter;
+pub use generation::{HookFile, LoadOptions, Template};
+pub use output::{DirectoryOutput, MemoryOutput, Output, TarOutput, ZipOutput};
+pub use plan::{ConflictStrategy, EntryKind, GenerationPlan, PlannedEntry, WrittenPlan};
+pub use update::{update, UpdateReport};
 pub use value::Value;
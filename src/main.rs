@@ -1,18 +1,27 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command as StdCommand;
+use std::time::Duration;
 
 use anyhow::{bail, Result};
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
-use kickstart::cli::prompt::{ask_bool, ask_choices, ask_integer, ask_string};
+use kickstart::cli::prompt::{
+    ask_bool, ask_choices, ask_float, ask_integer, ask_multi_choices, ask_secret, ask_string,
+    ask_version,
+};
 use kickstart::cli::terminal;
-use kickstart::{HookFile, Template, TemplateDefinition, Value};
+use kickstart::errors::ErrorKind;
+use kickstart::{
+    read_answers_file, update, Cache, Config, ConflictStrategy, EntryKind, HookFile, LoadOptions,
+    RecordedAnswers, Template, TemplateDefinition, Value, Variable, ANSWERS_FILENAME,
+};
 
 #[derive(Parser)]
 #[clap(version, author, about, subcommand_negates_reqs = true)]
 pub struct Cli {
-    /// Template to use: a local path or a HTTP url pointing to a Git repository
+    /// Template to use: an alias from the config, a local path, a .tar.gz/.tgz/.zip archive
+    /// or a HTTP url pointing to a Git repository
     #[clap(required = true)]
     pub template: Option<String>,
 
@@ -20,16 +29,60 @@ pub struct Cli {
     #[clap(short = 'o', long, default_value_os_t = PathBuf::from("."))]
     pub output_dir: PathBuf,
 
+    /// Write the project in a .tar.gz, .tgz or .zip archive instead of the output directory.
+    /// Hooks are not run in that case.
+    #[clap(long, conflicts_with = "record_answers")]
+    pub archive: Option<PathBuf>,
+
     /// The directory of the given folder/repository to use, which needs to be a template.
     /// Only really useful if you are loading a template from a repository. If you are loading
     /// from the filesystem you can directly point to the right folder.
     #[clap(short = 'd', long)]
     pub directory: Option<String>,
 
+    /// The branch, tag or commit SHA to use when loading the template from a Git repository.
+    /// Defaults to the default branch of the repository.
+    #[clap(long = "ref")]
+    pub git_ref: Option<String>,
+
+    /// Only use the templates already in the cache rather than fetching them
+    #[clap(long, default_value_t = false)]
+    pub offline: bool,
+
     /// Do not prompt for variables and only use the defaults from template.toml
     #[clap(long, default_value_t = false)]
     pub no_input: bool,
 
+    /// A TOML or JSON file with the values of some variables, eg `project_name = "hello"`.
+    /// The variables not in that file will be asked, or use their default with `--no-input`.
+    #[clap(long)]
+    pub answers: Option<PathBuf>,
+
+    /// Sets the value of a variable instead of asking for it, eg `--set project_name=hello`.
+    /// Values of multi-select questions are separated by commas, eg `--set features=ci,docs`.
+    /// Can be used several times and takes precedence over `--answers`.
+    #[clap(long = "set", value_name = "NAME=VALUE")]
+    pub set: Vec<String>,
+
+    /// Write the template source, revision and answers in a .kickstart-answers.toml file in the
+    /// output directory so the project can be generated again with `kickstart replay`
+    #[clap(long, default_value_t = false)]
+    pub record_answers: bool,
+
+    /// What to do with the files already in the output directory that would be overwritten
+    #[clap(long, value_enum, default_value_t = OnConflict::Fail)]
+    pub on_conflict: OnConflict,
+
+    /// Only print the files and directories that would be generated, without writing anything
+    /// or running the hooks
+    #[clap(long, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Undo the generation if a post-gen hook fails: generated files are removed and
+    /// overwritten ones restored
+    #[clap(long, default_value_t = false)]
+    pub rollback_on_hook_failure: bool,
+
     /// Whether to run all the hooks
     #[clap(long, default_value_t = true)]
     pub run_hooks: bool,
@@ -38,6 +91,18 @@ pub struct Cli {
     pub command: Option<Command>,
 }
 
+#[derive(Debug, Copy, Clone, ValueEnum)]
+pub enum OnConflict {
+    /// Stop before writing anything and list the existing files
+    Fail,
+    /// Keep the existing files
+    Skip,
+    /// Replace the existing files
+    Overwrite,
+    /// Ask for each existing file whether to replace it, they are kept with `--no-input`
+    Prompt,
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Command {
     /// Validates that a template.toml is valid
@@ -45,48 +110,114 @@ pub enum Command {
         /// The path to the template.toml
         path: PathBuf,
     },
+    /// Generates a project again from the answers file written with `--record-answers`
+    Replay {
+        /// The directory containing the answers file
+        dir: PathBuf,
+    },
+    /// Updates a project generated with `--record-answers` to a new version of its template,
+    /// keeping the changes made to the project
+    Update {
+        /// The directory containing the answers file
+        dir: PathBuf,
+        /// The branch, tag or commit SHA to update to. Defaults to the default branch
+        #[clap(long = "ref")]
+        git_ref: Option<String>,
+    },
+    /// Lists the templates defined in the user config
+    List,
+    /// Manages the cache of remote templates
+    Cache {
+        #[clap(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheCommand {
+    /// Lists the templates in the cache
+    List,
+    /// Removes templates from the cache
+    Prune {
+        /// Only remove the templates that haven't been used for that many days
+        #[clap(long)]
+        older_than: Option<u64>,
+    },
 }
 
 /// Ask all the questions of that template and return the answers.
+/// The variables in `answers` are not asked and use the value given there instead.
 /// If `no_input` is `true`, it will automatically pick the defaults without
 /// prompting the user
-fn ask_questions(template: &Template, no_input: bool) -> Result<HashMap<String, Value>> {
+fn ask_questions(
+    template: &Template,
+    no_input: bool,
+    answers: &HashMap<String, Value>,
+) -> Result<HashMap<String, Value>> {
+    for (name, value) in answers {
+        template.check_value(name, value)?;
+    }
+
     let mut vals = HashMap::new();
 
     for var in &template.definition.variables {
         if !template.should_ask_variable(&var.name, &vals)? {
             continue;
         }
+        if let Some(value) = answers.get(&var.name) {
+            vals.insert(var.name.clone(), var.coerce_value(value.clone()));
+            continue;
+        }
+        if let Some(value) = var.value_from_env()? {
+            vals.insert(var.name.clone(), value);
+            continue;
+        }
         let default = template.get_default_for(&var.name, &vals)?;
-
-        if let Some(ref choices) = var.choices {
-            let res = if no_input { default } else { ask_choices(&var.prompt, &default, choices)? };
-            vals.insert(var.name.clone(), res);
+        if no_input {
+            // Defaults can be rendered from other values so they might not be valid
+            var.check_value(&default)?;
+            vals.insert(var.name.clone(), default);
             continue;
         }
 
-        match default {
-            Value::Boolean(b) => {
-                let res = if no_input { b } else { ask_bool(&var.prompt, b)? };
-                vals.insert(var.name.clone(), Value::Boolean(res));
-                continue;
-            }
-            Value::String(s) => {
-                let res = if no_input { s } else { ask_string(&var.prompt, &s, &var.validation)? };
-                vals.insert(var.name.clone(), Value::String(res));
-                continue;
-            }
-            Value::Integer(i) => {
-                let res = if no_input { i } else { ask_integer(&var.prompt, i)? };
-                vals.insert(var.name.clone(), Value::Integer(res));
-                continue;
+        // The prompts only check the type, the choices and the regex, not the bounds
+        let res = loop {
+            let res = ask_variable(var, default.clone())?;
+            match var.check_value(&res) {
+                Ok(()) => break res,
+                Err(e) => terminal::error(&format!("{}\n", e)),
             }
-        }
+        };
+        vals.insert(var.name.clone(), res);
     }
 
     Ok(vals)
 }
 
+fn ask_variable(var: &Variable, default: Value) -> Result<Value> {
+    if let Some(ref choices) = var.choices {
+        return Ok(match default {
+            Value::Array(ref selected) => {
+                Value::Array(ask_multi_choices(&var.prompt, selected, choices)?)
+            }
+            _ => ask_choices(&var.prompt, &default, choices)?,
+        });
+    }
+
+    Ok(match default {
+        Value::Boolean(b) => Value::Boolean(ask_bool(&var.prompt, b)?),
+        Value::String(s) if var.secret => {
+            Value::String(ask_secret(&var.prompt, &s, &var.validation)?)
+        }
+        Value::String(s) => Value::String(ask_string(&var.prompt, &s, &var.validation)?),
+        Value::Integer(i) => Value::Integer(ask_integer(&var.prompt, i)?),
+        Value::Float(f) => Value::Float(ask_float(&var.prompt, f)?),
+        Value::Version(v) => Value::Version(ask_version(&var.prompt, v)?),
+        // Arrays without choices are invalid, there is nothing to ask
+        Value::Array(_) => default,
+    })
+}
+
 fn execute_hook(hook: &HookFile, output_dir: &PathBuf) -> Result<()> {
     terminal::bold(&format!("  - {}\n", hook.name()));
     let mut command = StdCommand::new(hook.path());
@@ -101,8 +232,130 @@ fn execute_hook(hook: &HookFile, output_dir: &PathBuf) -> Result<()> {
     }
 }
 
+/// How to run the generation, mostly from the CLI flags
+struct GenerateOptions {
+    on_conflict: OnConflict,
+    run_hooks: bool,
+    /// Write the answers file in the output directory
+    record_answers: bool,
+    rollback_on_hook_failure: bool,
+}
+
+impl GenerateOptions {
+    fn from_cli(cli: &Cli) -> GenerateOptions {
+        GenerateOptions {
+            // Nobody can answer with `--no-input`, so we use the default answer of the prompt
+            on_conflict: match cli.on_conflict {
+                OnConflict::Prompt if cli.no_input => OnConflict::Skip,
+                on_conflict => on_conflict,
+            },
+            run_hooks: cli.run_hooks,
+            record_answers: cli.record_answers,
+            rollback_on_hook_failure: cli.rollback_on_hook_failure,
+        }
+    }
+}
+
+/// Runs the hooks and generates the template, whose variables are already set.
+fn generate(template: &Template, output_dir: &PathBuf, options: &GenerateOptions) -> Result<()> {
+    // 1. check the existing files before doing anything
+    let mut plan = template.plan(output_dir)?;
+    let skipped = match options.on_conflict {
+        OnConflict::Prompt => {
+            let mut skipped = Vec::new();
+            for path in plan.conflicts(output_dir) {
+                let prompt = format!("{} already exists, overwrite it?", path.display());
+                if !ask_bool(&prompt, false)? {
+                    skipped.push(path);
+                }
+            }
+            plan.skip(&skipped);
+            skipped
+        }
+        OnConflict::Fail => plan.resolve_conflicts(output_dir, ConflictStrategy::Fail)?,
+        OnConflict::Skip => plan.resolve_conflicts(output_dir, ConflictStrategy::Skip)?,
+        OnConflict::Overwrite => plan.resolve_conflicts(output_dir, ConflictStrategy::Overwrite)?,
+    };
+    for path in &skipped {
+        println!("Skipped existing file: {}", path.display());
+    }
+
+    // 2. run pre-gen hooks
+    let pre_gen_hooks = template.get_pre_gen_hooks()?;
+    if options.run_hooks && !pre_gen_hooks.is_empty() {
+        terminal::bold("Running pre-gen hooks...\n");
+        for hook in &pre_gen_hooks {
+            execute_hook(hook, output_dir)?;
+        }
+        // For spacing
+        println!();
+    }
+
+    // 3. generate
+    let written = plan.write_with_rollback(output_dir)?;
+
+    // 4. run post-gen hooks
+    let post_gen_hooks = template.get_post_gen_hooks()?;
+    if options.run_hooks && !post_gen_hooks.is_empty() {
+        terminal::bold("Running post-gen hooks...\n");
+        for hook in &post_gen_hooks {
+            if let Err(e) = execute_hook(hook, output_dir) {
+                if options.rollback_on_hook_failure {
+                    written.rollback()?;
+                    terminal::error("The generation was rolled back\n");
+                }
+                return Err(e);
+            }
+        }
+        // For spacing
+        println!();
+    }
+    written.commit();
+
+    // 5. record the answers
+    if options.record_answers {
+        template.write_answers(output_dir)?;
+    }
+
+    Ok(())
+}
+
+/// Prints what generating the template would write, without writing anything
+fn print_plan(template: &Template, output_dir: &Path) -> Result<()> {
+    let plan = template.plan(output_dir)?;
+    terminal::bold(&format!("Would generate in {}:\n", output_dir.display()));
+    for entry in &plan.entries {
+        match entry.kind {
+            EntryKind::Directory => {
+                println!("  {:<10} {:>10}  {}/", entry.kind.as_str(), "", entry.path.display())
+            }
+            EntryKind::Symlink => println!(
+                "  {:<10} {:>10}  {} -> {}",
+                entry.kind.as_str(),
+                "",
+                entry.path.display(),
+                entry.link_target.as_deref().unwrap_or_else(|| Path::new("")).display()
+            ),
+            _ => println!(
+                "  {:<10} {:>10}  {}",
+                entry.kind.as_str(),
+                entry.size,
+                entry.path.display()
+            ),
+        }
+    }
+    if !plan.cleaned_up.is_empty() {
+        terminal::bold("\nSkipped because of the cleanup:\n");
+        for path in &plan.cleaned_up {
+            println!("  {}", path.display());
+        }
+    }
+    Ok(())
+}
+
 fn try_main() -> Result<()> {
     let cli = Cli::parse();
+    let generate_options = GenerateOptions::from_cli(&cli);
 
     match cli.command {
         Some(Command::Validate { path }) => {
@@ -119,39 +372,151 @@ fn try_main() -> Result<()> {
                 terminal::success("The template.toml file is valid!\n");
             }
         }
-        None => {
-            let mut template =
-                Template::from_input(&cli.template.unwrap(), cli.directory.as_deref())?;
+        Some(Command::Replay { dir }) => {
+            let answers = RecordedAnswers::from_file(&dir.join(ANSWERS_FILENAME))?;
+            let cache = Cache::default_location().offline(cli.offline);
+            let template = Template::from_answers(&answers, &cache)?;
+            let options = GenerateOptions { record_answers: true, ..generate_options };
+            generate(&template, &dir, &options)?;
 
-            // 1. ask questions
-            let vals = ask_questions(&template, cli.no_input)?;
-            template.set_variables(vals)?;
+            terminal::success("\nEverything done, ready to go!\n");
+        }
+        Some(Command::Update { dir, git_ref }) => {
+            let answers = RecordedAnswers::from_file(&dir.join(ANSWERS_FILENAME))?;
+            if answers.revision.is_none() {
+                bail!("Only projects generated from a Git repository can be updated");
+            }
+            let cache = Cache::default_location().offline(cli.offline);
+            let old = Template::from_answers(&answers, &cache)?;
+            let options = LoadOptions {
+                directory: answers.directory.clone(),
+                // `HEAD` is the default branch of the repository
+                git_ref: Some(git_ref.unwrap_or_else(|| "HEAD".to_string())),
+                cache,
+                ..LoadOptions::default()
+            };
+            let mut new = Template::from_input_with_options(&answers.source, &options)?;
+            new.set_variables_from_previous(&answers.variables)?;
+
+            let report = update(&dir, &old, &new)?;
+            new.write_answers(&dir)?;
 
-            // 2. run pre-gen hooks
-            let pre_gen_hooks = template.get_pre_gen_hooks()?;
-            if cli.run_hooks && !pre_gen_hooks.is_empty() {
-                terminal::bold("Running pre-gen hooks...\n");
-                for hook in &pre_gen_hooks {
-                    execute_hook(hook, &cli.output_dir)?;
+            for (title, paths) in [
+                ("Updated", &report.updated),
+                ("Added", &report.added),
+                ("Removed", &report.removed),
+            ] {
+                for path in paths {
+                    println!("{}: {}", title, path.display());
+                }
+            }
+            if !report.conflicts.is_empty() {
+                // The answers are still recorded: the merged files already come from the new
+                // revision, which is the base of the next update
+                let err = format!(
+                    "{} file(s) have conflicts to resolve by hand, the new revision of the template \
+                     was recorded so they will not be reported again:\n{}",
+                    report.conflicts.len(),
+                    report
+                        .conflicts
+                        .iter()
+                        .map(|p| format!("- {}", p.display()))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                );
+                bail!(err);
+            }
+            terminal::success("\nEverything done, the project is up to date!\n");
+        }
+        Some(Command::List) => {
+            let config = Config::load()?;
+            if config.templates.is_empty() {
+                let path = Config::default_path().unwrap_or_default();
+                println!("No templates defined in {}", path.display());
+            }
+            // Only the templates already in the cache are read, nothing is fetched
+            let options = LoadOptions {
+                cache: Cache::default_location().offline(true),
+                config,
+                ..LoadOptions::default()
+            };
+            for (name, alias) in &options.config.templates {
+                terminal::bold(name);
+                print!(" - {}", alias.url);
+                if let Some(ref git_ref) = alias.git_ref {
+                    print!(" @ {}", git_ref);
                 }
-                // For spacing
                 println!();
+                match Template::from_input_with_options(name, &options) {
+                    Ok(template) => {
+                        if let Some(description) = template.definition.description {
+                            println!("  {}", description);
+                        }
+                    }
+                    Err(e) if matches!(e.kind, ErrorKind::NotCached { .. }) => (),
+                    Err(e) => terminal::error(&format!("  Could not load the template: {}\n", e)),
+                }
             }
+        }
+        Some(Command::Cache { command: CacheCommand::List }) => {
+            let cache = Cache::default_location();
+            let entries = cache.entries()?;
+            if entries.is_empty() {
+                println!("No templates in the cache ({})", cache.root().display());
+            }
+            for entry in entries {
+                terminal::bold(&entry.remote);
+                if let Some(git_ref) = entry.git_ref {
+                    print!(" @ {}", git_ref);
+                }
+                println!("\n  {}", entry.path.display());
+            }
+        }
+        Some(Command::Cache { command: CacheCommand::Prune { older_than } }) => {
+            let max_age = older_than.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+            let removed = Cache::default_location().prune(max_age)?;
+            terminal::success(&format!("Removed {} template(s) from the cache\n", removed.len()));
+        }
+        None => {
+            let options = LoadOptions {
+                directory: cli.directory,
+                git_ref: cli.git_ref,
+                cache: Cache::default_location().offline(cli.offline),
+                config: Config::load()?,
+            };
+            let mut template = Template::from_input_with_options(&cli.template.unwrap(), &options)?;
 
-            // 3. generate
-            template.generate(&cli.output_dir)?;
+            // Ask questions
+            let mut answers = match cli.answers {
+                Some(ref path) => read_answers_file(path)?,
+                None => HashMap::new(),
+            };
+            for set in &cli.set {
+                let Some((name, input)) = set.split_once('=') else {
+                    bail!("Invalid `--set {}`: expected the format `name=value`", set);
+                };
+                answers.insert(name.to_string(), template.parse_value(name, input)?);
+            }
+            let vals = ask_questions(&template, cli.no_input, &answers)?;
+            template.set_variables(vals)?;
 
-            // 4. run post-gen hooks
-            let post_gen_hooks = template.get_post_gen_hooks()?;
-            if cli.run_hooks && !post_gen_hooks.is_empty() {
-                terminal::bold("Running post-gen hooks...\n");
-                for hook in &post_gen_hooks {
-                    execute_hook(hook, &cli.output_dir)?;
+            if cli.dry_run {
+                return print_plan(&template, &cli.output_dir);
+            }
+            if let Some(ref path) = cli.archive {
+                template.generate_archive(path)?;
+                let mut hooks = template.get_pre_gen_hooks()?;
+                hooks.extend(template.get_post_gen_hooks()?);
+                if !hooks.is_empty() {
+                    println!("Hooks are not run with --archive, skipped:");
+                    for hook in &hooks {
+                        println!("  - {}", hook.name());
+                    }
                 }
-                // For spacing
-                println!();
+                terminal::success(&format!("\nProject written in {}\n", path.display()));
+                return Ok(());
             }
-
+            generate(&template, &cli.output_dir, &generate_options)?;
             terminal::success("\nEverything done, ready to go!\n");
         }
     }
@@ -0,0 +1,268 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use zip::write::SimpleFileOptions;
+
+use crate::errors::{map_io_err, Result};
+use crate::utils::create_directory;
+
+/// The mode of files without one, eg rendered files
+const DEFAULT_FILE_MODE: u32 = 0o644;
+const DEFAULT_DIRECTORY_MODE: u32 = 0o755;
+
+/// Where a generated project is written, see [crate::Template::generate_to].
+/// Directories are always created before the files and directories they contain.
+pub trait Output {
+    /// Creates a directory, `path` being relative to the root of the project
+    fn create_dir(&mut self, path: &Path) -> Result<()>;
+
+    /// Writes a file, `path` being relative to the root of the project.
+    /// `mode` is the unix mode of the file when it's known.
+    fn write_file(&mut self, path: &Path, contents: &[u8], mode: Option<u32>) -> Result<()>;
+
+    /// Creates a symlink pointing to `target`, which is relative to the link
+    fn create_symlink(&mut self, path: &Path, target: &Path) -> Result<()>;
+}
+
+/// Writes the project in a directory, overwriting existing files
+#[derive(Debug, Clone)]
+pub struct DirectoryOutput {
+    root: PathBuf,
+}
+
+impl DirectoryOutput {
+    pub fn new(root: &Path) -> DirectoryOutput {
+        DirectoryOutput { root: root.to_path_buf() }
+    }
+}
+
+impl Output for DirectoryOutput {
+    fn create_dir(&mut self, path: &Path) -> Result<()> {
+        create_directory(&self.root.join(path))
+    }
+
+    fn write_file(&mut self, path: &Path, contents: &[u8], mode: Option<u32>) -> Result<()> {
+        let real_path = self.root.join(path);
+        map_io_err(fs::write(&real_path, contents), &real_path)?;
+
+        #[cfg(unix)]
+        if let Some(mode) = mode {
+            use std::os::unix::fs::PermissionsExt;
+            map_io_err(
+                fs::set_permissions(&real_path, fs::Permissions::from_mode(mode)),
+                &real_path,
+            )?;
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+
+        Ok(())
+    }
+
+    fn create_symlink(&mut self, path: &Path, target: &Path) -> Result<()> {
+        let real_path = self.root.join(path);
+        #[cfg(unix)]
+        let res = std::os::unix::fs::symlink(target, &real_path);
+        #[cfg(not(unix))]
+        let res = {
+            let _ = target;
+            Err(io::Error::new(io::ErrorKind::Unsupported, "symlinks can only be created on unix"))
+        };
+        map_io_err(res, &real_path)
+    }
+}
+
+/// Keeps the project in memory. File modes are not kept.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryOutput {
+    /// The content of every file, by path relative to the root of the project
+    pub files: BTreeMap<PathBuf, Vec<u8>>,
+    /// All the directories, including the empty ones
+    pub directories: BTreeSet<PathBuf>,
+    /// The target of every symlink, by path relative to the root of the project
+    pub symlinks: BTreeMap<PathBuf, PathBuf>,
+}
+
+impl Output for MemoryOutput {
+    fn create_dir(&mut self, path: &Path) -> Result<()> {
+        self.directories.insert(path.to_path_buf());
+        Ok(())
+    }
+
+    fn write_file(&mut self, path: &Path, contents: &[u8], _mode: Option<u32>) -> Result<()> {
+        self.files.insert(path.to_path_buf(), contents.to_vec());
+        Ok(())
+    }
+
+    fn create_symlink(&mut self, path: &Path, target: &Path) -> Result<()> {
+        self.symlinks.insert(path.to_path_buf(), target.to_path_buf());
+        Ok(())
+    }
+}
+
+/// Writes the project as a tar archive. Wrap the writer in a `flate2::write::GzEncoder`
+/// to get a `.tar.gz`.
+pub struct TarOutput<W: Write> {
+    builder: tar::Builder<W>,
+    mtime: u64,
+}
+
+impl<W: Write> TarOutput<W> {
+    pub fn new(writer: W) -> TarOutput<W> {
+        let mtime = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        TarOutput { builder: tar::Builder::new(writer), mtime }
+    }
+
+    /// Writes the end of the archive and returns the writer
+    pub fn finish(self) -> Result<W> {
+        map_io_err(self.builder.into_inner(), Path::new(""))
+    }
+
+    fn append(
+        &mut self,
+        path: &Path,
+        contents: &[u8],
+        kind: tar::EntryType,
+        mode: u32,
+    ) -> Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(kind);
+        header.set_size(contents.len() as u64);
+        header.set_mode(mode);
+        header.set_mtime(self.mtime);
+        map_io_err(self.builder.append_data(&mut header, path, contents), path)
+    }
+}
+
+impl<W: Write> Output for TarOutput<W> {
+    fn create_dir(&mut self, path: &Path) -> Result<()> {
+        self.append(path, &[], tar::EntryType::Directory, DEFAULT_DIRECTORY_MODE)
+    }
+
+    fn write_file(&mut self, path: &Path, contents: &[u8], mode: Option<u32>) -> Result<()> {
+        self.append(path, contents, tar::EntryType::Regular, mode.unwrap_or(DEFAULT_FILE_MODE))
+    }
+
+    fn create_symlink(&mut self, path: &Path, target: &Path) -> Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        header.set_mtime(self.mtime);
+        map_io_err(self.builder.append_link(&mut header, path, target), path)
+    }
+}
+
+/// Writes the project as a zip archive
+pub struct ZipOutput<W: Write + Seek> {
+    writer: zip::ZipWriter<W>,
+}
+
+impl<W: Write + Seek> ZipOutput<W> {
+    pub fn new(writer: W) -> ZipOutput<W> {
+        ZipOutput { writer: zip::ZipWriter::new(writer) }
+    }
+
+    /// Writes the end of the archive and returns the writer
+    pub fn finish(self) -> Result<W> {
+        map_io_err(self.writer.finish().map_err(io::Error::other), Path::new(""))
+    }
+}
+
+/// Zip archives always use `/` as separator
+fn zip_name(path: &Path) -> String {
+    path.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+impl<W: Write + Seek> Output for ZipOutput<W> {
+    fn create_dir(&mut self, path: &Path) -> Result<()> {
+        let options = SimpleFileOptions::default().unix_permissions(DEFAULT_DIRECTORY_MODE);
+        map_io_err(
+            self.writer.add_directory(zip_name(path), options).map_err(io::Error::other),
+            path,
+        )
+    }
+
+    fn write_file(&mut self, path: &Path, contents: &[u8], mode: Option<u32>) -> Result<()> {
+        let options =
+            SimpleFileOptions::default().unix_permissions(mode.unwrap_or(DEFAULT_FILE_MODE));
+        map_io_err(
+            self.writer.start_file(zip_name(path), options).map_err(io::Error::other),
+            path,
+        )?;
+        map_io_err(self.writer.write_all(contents), path)
+    }
+
+    fn create_symlink(&mut self, path: &Path, target: &Path) -> Result<()> {
+        let options = SimpleFileOptions::default();
+        map_io_err(
+            self.writer
+                .add_symlink(zip_name(path), zip_name(target), options)
+                .map_err(io::Error::other),
+            path,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::*;
+
+    fn write_project(output: &mut dyn Output) {
+        output.create_dir(Path::new("project")).unwrap();
+        output.write_file(Path::new("project/README.md"), b"Hello", None).unwrap();
+        output.write_file(Path::new("project/run.sh"), b"#!/bin/sh", Some(0o755)).unwrap();
+        output.create_symlink(Path::new("project/start.sh"), Path::new("run.sh")).unwrap();
+    }
+
+    #[test]
+    fn can_write_tar() {
+        let mut output = TarOutput::new(Vec::new());
+        write_project(&mut output);
+        let bytes = output.finish().unwrap();
+
+        let mut archive = tar::Archive::new(bytes.as_slice());
+        let mut found = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).unwrap();
+            let path = entry.path().unwrap().to_path_buf();
+            if let Some(target) = entry.link_name().unwrap() {
+                contents = target.display().to_string();
+            }
+            found.push((path, entry.header().mode().unwrap(), contents));
+        }
+        assert_eq!(
+            found,
+            vec![
+                (PathBuf::from("project"), 0o755, String::new()),
+                (PathBuf::from("project/README.md"), 0o644, "Hello".to_string()),
+                (PathBuf::from("project/run.sh"), 0o755, "#!/bin/sh".to_string()),
+                (PathBuf::from("project/start.sh"), 0o777, "run.sh".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_write_zip() {
+        let mut output = ZipOutput::new(Cursor::new(Vec::new()));
+        write_project(&mut output);
+        let cursor = output.finish().unwrap();
+
+        let mut archive = zip::ZipArchive::new(cursor).unwrap();
+        assert_eq!(archive.len(), 4);
+        assert!(archive.by_name("project/start.sh").unwrap().is_symlink());
+        assert!(archive.by_name("project/").unwrap().is_dir());
+        let mut script = archive.by_name("project/run.sh").unwrap();
+        assert_eq!(script.unix_mode().map(|m| m & 0o777), Some(0o755));
+        let mut contents = String::new();
+        script.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "#!/bin/sh");
+    }
+}
@@ -0,0 +1,467 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tempfile::{tempdir, TempDir};
+
+use crate::errors::{map_io_err, new_error, ErrorKind, Result};
+use crate::output::{DirectoryOutput, Output};
+use crate::utils::create_directory;
+
+/// What a planned entry will end up as in the output
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EntryKind {
+    /// A directory to create
+    Directory,
+    /// A file passed through Tera
+    Rendered,
+    /// A file copied as is, because it's binary or matches `copy_without_render`
+    Copied,
+    /// A symlink, with `recreate_symlinks`
+    Symlink,
+}
+
+impl EntryKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntryKind::Directory => "directory",
+            EntryKind::Rendered => "rendered",
+            EntryKind::Copied => "copied",
+            EntryKind::Symlink => "symlink",
+        }
+    }
+}
+
+/// What to do with the files of the output directory that the generation would overwrite
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum ConflictStrategy {
+    /// Error without writing anything, listing all the conflicting paths
+    #[default]
+    Fail,
+    /// Keep the existing files and only write the other ones
+    Skip,
+    /// Replace the existing files
+    Overwrite,
+}
+
+/// A file or directory the generation will write
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedEntry {
+    /// Where it will be written, relative to the output directory
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    /// The size in bytes of the file to write, 0 for directories
+    pub size: u64,
+    /// The path of the file or directory in the template
+    pub source: PathBuf,
+    /// The unix mode to give to the file, if known
+    pub mode: Option<u32>,
+    /// Where symlinks point to
+    pub link_target: Option<PathBuf>,
+    /// The content of rendered files, and of copied files when it differs from the source
+    contents: Option<Vec<u8>>,
+}
+
+impl PlannedEntry {
+    pub(crate) fn directory(path: PathBuf, source: PathBuf) -> PlannedEntry {
+        PlannedEntry {
+            path,
+            kind: EntryKind::Directory,
+            size: 0,
+            source,
+            mode: None,
+            link_target: None,
+            contents: None,
+        }
+    }
+
+    pub(crate) fn copied(
+        path: PathBuf,
+        source: PathBuf,
+        size: u64,
+        mode: Option<u32>,
+    ) -> PlannedEntry {
+        PlannedEntry {
+            path,
+            kind: EntryKind::Copied,
+            size,
+            source,
+            mode,
+            link_target: None,
+            contents: None,
+        }
+    }
+
+    pub(crate) fn rendered(
+        path: PathBuf,
+        source: PathBuf,
+        content: String,
+        mode: Option<u32>,
+    ) -> PlannedEntry {
+        PlannedEntry {
+            path,
+            kind: EntryKind::Rendered,
+            size: content.len() as u64,
+            source,
+            mode,
+            link_target: None,
+            contents: Some(content.into_bytes()),
+        }
+    }
+
+    /// Copies those contents rather than the source file, eg when its front matter is removed
+    pub(crate) fn with_contents(mut self, contents: Vec<u8>) -> PlannedEntry {
+        self.size = contents.len() as u64;
+        self.contents = Some(contents);
+        self
+    }
+
+    pub(crate) fn symlink(path: PathBuf, source: PathBuf, target: PathBuf) -> PlannedEntry {
+        PlannedEntry {
+            path,
+            kind: EntryKind::Symlink,
+            size: 0,
+            source,
+            mode: None,
+            link_target: Some(target),
+            contents: None,
+        }
+    }
+
+    /// Whether `path`, which exists, is already what this entry would write
+    fn is_written_at(&self, path: &Path, meta: &fs::Metadata) -> bool {
+        match self.kind {
+            EntryKind::Directory => meta.is_dir(),
+            EntryKind::Symlink => {
+                meta.file_type().is_symlink() && fs::read_link(path).ok() == self.link_target
+            }
+            EntryKind::Rendered | EntryKind::Copied => {
+                if !meta.is_file() || meta.len() != self.size {
+                    return false;
+                }
+                let existing = fs::read(path).ok();
+                match self.contents {
+                    Some(ref contents) => existing.as_ref() == Some(contents),
+                    None => existing.is_some() && existing == fs::read(&self.source).ok(),
+                }
+            }
+        }
+    }
+}
+
+/// Everything a generation would do, computed without writing anything.
+/// See [crate::Template::plan].
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationPlan {
+    /// The files and directories to write, parents always come before their children
+    pub entries: Vec<PlannedEntry>,
+    /// The entries that are not written because of the `cleanup` of the template
+    pub cleaned_up: Vec<PathBuf>,
+}
+
+impl GenerationPlan {
+    /// The entries that already exist in the output directory and would be overwritten.
+    /// Existing directories are not conflicts, unless a file would be written in their place,
+    /// and neither are existing files with the same content as the one to write.
+    pub fn conflicts(&self, output_dir: &Path) -> Vec<PathBuf> {
+        self.entries
+            .iter()
+            .filter(|e| {
+                let path = output_dir.join(&e.path);
+                match fs::symlink_metadata(&path) {
+                    Ok(meta) => !e.is_written_at(&path, &meta),
+                    Err(_) => false,
+                }
+            })
+            .map(|e| e.path.clone())
+            .collect()
+    }
+
+    /// Removes those paths, and everything in them for directories, from the plan
+    pub fn skip(&mut self, paths: &[PathBuf]) {
+        self.entries.retain(|e| !paths.iter().any(|p| e.path.starts_with(p)));
+    }
+
+    /// Applies the strategy to the conflicts with the output directory and returns the paths
+    /// that will not be written
+    pub fn resolve_conflicts(
+        &mut self,
+        output_dir: &Path,
+        strategy: ConflictStrategy,
+    ) -> Result<Vec<PathBuf>> {
+        let conflicts = self.conflicts(output_dir);
+        if conflicts.is_empty() {
+            return Ok(conflicts);
+        }
+
+        match strategy {
+            ConflictStrategy::Fail => Err(new_error(ErrorKind::Conflicts { paths: conflicts })),
+            ConflictStrategy::Skip => {
+                self.skip(&conflicts);
+                Ok(conflicts)
+            }
+            ConflictStrategy::Overwrite => Ok(Vec::new()),
+        }
+    }
+
+    /// Writes all the entries of the plan in the given directory, overwriting existing files.
+    /// See [GenerationPlan::resolve_conflicts] to avoid that.
+    /// Nothing is changed in the output directory if writing any of the entries fails.
+    pub fn write(&self, output_dir: &Path) -> Result<()> {
+        self.write_with_rollback(output_dir)?.commit();
+        Ok(())
+    }
+
+    /// Like [GenerationPlan::write] but the returned [WrittenPlan] can restore the output
+    /// directory to its previous state, eg if a post-gen hook fails.
+    ///
+    /// Everything is first written in a staging directory and only moved in the output
+    /// directory once it all succeeded.
+    pub fn write_with_rollback(&self, output_dir: &Path) -> Result<WrittenPlan> {
+        let mut written = WrittenPlan {
+            output_dir: output_dir.to_path_buf(),
+            created_output_dir: !output_dir.exists(),
+            created: Vec::new(),
+            overwritten: Vec::new(),
+            backup: None,
+        };
+        create_directory(output_dir)?;
+
+        if let Err(e) = written.write_staged(self) {
+            let _ = written.rollback();
+            return Err(e);
+        }
+
+        Ok(written)
+    }
+
+    /// Writes all the entries of the plan through the given output, eg to an archive
+    pub fn write_to(&self, output: &mut dyn Output) -> Result<()> {
+        for entry in &self.entries {
+            match entry.kind {
+                EntryKind::Directory => output.create_dir(&entry.path)?,
+                EntryKind::Copied if entry.contents.is_none() => {
+                    let contents = map_io_err(fs::read(&entry.source), &entry.source)?;
+                    output.write_file(&entry.path, &contents, entry.mode)?;
+                }
+                EntryKind::Copied | EntryKind::Rendered => {
+                    let contents = entry.contents.as_deref().unwrap_or_default();
+                    output.write_file(&entry.path, contents, entry.mode)?;
+                }
+                EntryKind::Symlink => {
+                    let target = entry.link_target.as_deref().unwrap_or_else(|| Path::new(""));
+                    output.create_symlink(&entry.path, target)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The changes made to the output directory by [GenerationPlan::write_with_rollback].
+/// They are kept when it is dropped or [committed](WrittenPlan::commit).
+#[derive(Debug)]
+pub struct WrittenPlan {
+    output_dir: PathBuf,
+    created_output_dir: bool,
+    /// Files and directories that didn't exist before, in creation order
+    created: Vec<PathBuf>,
+    /// Files that existed before, with a copy in `backup`
+    overwritten: Vec<PathBuf>,
+    backup: Option<TempDir>,
+}
+
+impl WrittenPlan {
+    fn write_staged(&mut self, plan: &GenerationPlan) -> Result<()> {
+        // The staging directory is in the output directory so the entries can be renamed
+        // rather than copied
+        let staging = map_io_err(
+            tempfile::Builder::new().prefix(".kickstart-staging").tempdir_in(&self.output_dir),
+            &self.output_dir,
+        )?;
+        plan.write_to(&mut DirectoryOutput::new(staging.path()))?;
+        self.move_entries(&plan.entries, staging.path())
+    }
+
+    fn move_entries(&mut self, entries: &[PlannedEntry], staging: &Path) -> Result<()> {
+        for entry in entries {
+            let target = self.output_dir.join(&entry.path);
+            if entry.kind == EntryKind::Directory {
+                if !target.is_dir() {
+                    map_io_err(fs::create_dir(&target), &target)?;
+                    self.created.push(entry.path.clone());
+                }
+                continue;
+            }
+
+            if target.exists() {
+                // Copied rather than renamed since the backup can be on another filesystem
+                let backup = match self.backup {
+                    Some(ref b) => b,
+                    None => self.backup.insert(tempdir()?),
+                };
+                let backup_path = backup.path().join(self.overwritten.len().to_string());
+                map_io_err(fs::copy(&target, &backup_path), &target)?;
+                self.overwritten.push(entry.path.clone());
+            } else {
+                self.created.push(entry.path.clone());
+            }
+            let staged = staging.join(&entry.path);
+            map_io_err(fs::rename(&staged, &target), &staged)?;
+        }
+
+        Ok(())
+    }
+
+    /// Keeps the changes
+    pub fn commit(self) {}
+
+    /// Removes the files and directories created and restores the ones overwritten.
+    /// Directories created are removed with everything in them, including files added by hooks.
+    pub fn rollback(self) -> Result<()> {
+        if self.created_output_dir {
+            return map_io_err(fs::remove_dir_all(&self.output_dir), &self.output_dir);
+        }
+
+        for path in self.created.iter().rev() {
+            let real_path = self.output_dir.join(path);
+            // Not following symlinks, which might point to a directory
+            match fs::symlink_metadata(&real_path) {
+                Ok(meta) if meta.is_dir() => {
+                    map_io_err(fs::remove_dir_all(&real_path), &real_path)?;
+                }
+                Ok(_) => map_io_err(fs::remove_file(&real_path), &real_path)?,
+                Err(_) => {}
+            }
+        }
+        if let Some(ref backup) = self.backup {
+            for (i, path) in self.overwritten.iter().enumerate() {
+                let real_path = self.output_dir.join(path);
+                map_io_err(fs::copy(backup.path().join(i.to_string()), &real_path), &real_path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_plan(source: &Path) -> GenerationPlan {
+        fs::write(source, "binary").unwrap();
+        GenerationPlan {
+            entries: vec![
+                PlannedEntry::directory(PathBuf::from("project"), source.to_path_buf()),
+                PlannedEntry::rendered(
+                    PathBuf::from("project/README.md"),
+                    source.to_path_buf(),
+                    "new".to_string(),
+                    None,
+                ),
+                PlannedEntry::copied(
+                    PathBuf::from("project/logo.png"),
+                    source.to_path_buf(),
+                    6,
+                    None,
+                ),
+                PlannedEntry::directory(PathBuf::from("project/docs"), source.to_path_buf()),
+                PlannedEntry::rendered(
+                    PathBuf::from("project/docs/index.md"),
+                    source.to_path_buf(),
+                    "new".to_string(),
+                    None,
+                ),
+            ],
+            cleaned_up: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn can_resolve_conflicts() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source");
+        let output = dir.path().join("output");
+        fs::create_dir_all(output.join("project")).unwrap();
+        fs::write(output.join("project").join("README.md"), "mine").unwrap();
+        // A file where a directory would be created
+        fs::write(output.join("project").join("docs"), "mine").unwrap();
+        // Files that would be written with the same content are not conflicts
+        fs::write(output.join("project").join("logo.png"), "binary").unwrap();
+
+        let expected = vec![PathBuf::from("project/README.md"), PathBuf::from("project/docs")];
+        let mut plan = create_plan(&source);
+        assert_eq!(plan.conflicts(&output), expected);
+
+        let err = plan.resolve_conflicts(&output, ConflictStrategy::Fail).unwrap_err();
+        match err.kind {
+            ErrorKind::Conflicts { paths } => assert_eq!(paths, expected),
+            _ => panic!("unexpected error: {:?}", err),
+        }
+
+        assert!(plan.resolve_conflicts(&output, ConflictStrategy::Overwrite).unwrap().is_empty());
+        assert_eq!(plan.entries.len(), 5);
+
+        assert_eq!(plan.resolve_conflicts(&output, ConflictStrategy::Skip).unwrap(), expected);
+        let paths: Vec<_> = plan.entries.iter().map(|e| e.path.clone()).collect();
+        assert_eq!(paths, vec![PathBuf::from("project"), PathBuf::from("project/logo.png")]);
+        plan.write(&output).unwrap();
+        assert_eq!(fs::read_to_string(output.join("project").join("README.md")).unwrap(), "mine");
+        assert!(output.join("project").join("logo.png").exists());
+    }
+
+    fn list_dir(dir: &Path) -> Vec<PathBuf> {
+        let mut paths: Vec<_> = walkdir::WalkDir::new(dir)
+            .into_iter()
+            .map(|e| e.unwrap().path().strip_prefix(dir).unwrap().to_path_buf())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn can_rollback_written_plan() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source");
+        let output = dir.path().join("output");
+        fs::create_dir_all(output.join("project")).unwrap();
+        fs::write(output.join("project").join("README.md"), "mine").unwrap();
+        let before = list_dir(&output);
+
+        let plan = create_plan(&source);
+        let written = plan.write_with_rollback(&output).unwrap();
+        assert_eq!(fs::read_to_string(output.join("project").join("README.md")).unwrap(), "new");
+        assert!(output.join("project").join("docs").join("index.md").exists());
+        // Like a file added by a post-gen hook
+        fs::write(output.join("project").join("docs").join("hook.md"), "hook").unwrap();
+
+        written.rollback().unwrap();
+        assert_eq!(list_dir(&output), before);
+        assert_eq!(fs::read_to_string(output.join("project").join("README.md")).unwrap(), "mine");
+    }
+
+    #[test]
+    fn writes_nothing_on_failure() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source");
+        let output = dir.path().join("output");
+        fs::create_dir_all(&output).unwrap();
+        fs::write(output.join("existing.md"), "mine").unwrap();
+
+        let mut plan = create_plan(&source);
+        plan.entries.push(PlannedEntry::copied(
+            PathBuf::from("project/missing.png"),
+            dir.path().join("missing"),
+            6,
+            None,
+        ));
+        assert!(plan.write(&output).is_err());
+        assert_eq!(list_dir(&output), vec![PathBuf::from(""), PathBuf::from("existing.md")]);
+
+        // The output directory is removed if it was created by the generation
+        let new_output = dir.path().join("new");
+        assert!(plan.write(&new_output).is_err());
+        assert!(!new_output.exists());
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tempfile::tempdir;
+use walkdir::WalkDir;
+
+use crate::answers::ANSWERS_FILENAME;
+use crate::errors::{map_io_err, Result};
+use crate::git;
+use crate::utils::{create_directory, file_mode, is_binary};
+use crate::Template;
+
+/// What happened to the files of a project during an [update]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UpdateReport {
+    /// Files changed in the template that were updated in the project, including clean merges
+    pub updated: Vec<PathBuf>,
+    /// Files added in the new version of the template
+    pub added: Vec<PathBuf>,
+    /// Files removed from the template that were deleted from the project
+    pub removed: Vec<PathBuf>,
+    /// Files that couldn't be merged automatically.
+    /// Text files contain conflict markers, binary files are left untouched.
+    /// Files changed in the project but removed from the template, or deleted in the project
+    /// but changed in the template, are left as they are in the project.
+    pub conflicts: Vec<PathBuf>,
+}
+
+/// All the files in that directory, relative to it
+fn list_files(dir: &Path) -> BTreeSet<PathBuf> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| !e.file_type().is_dir())
+        .map(|e| e.path().strip_prefix(dir).expect("valid path").to_path_buf())
+        .filter(|p| p != Path::new(ANSWERS_FILENAME))
+        .collect()
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    map_io_err(fs::read(path), path)
+}
+
+fn copy(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        create_directory(parent)?;
+    }
+    map_io_err(fs::copy(from, to), from)?;
+    Ok(())
+}
+
+/// Gives `project` the mode of `new` if the template changed it and the project didn't.
+/// Returns whether the mode of `project` changed.
+fn update_mode(project: &Path, old: &Path, new: &Path) -> Result<bool> {
+    let mode = |path: &Path| map_io_err(fs::metadata(path), path).map(|m| file_mode(&m));
+    let (old_mode, new_mode) = (mode(old)?, mode(new)?);
+    if old_mode == new_mode || mode(project)? != old_mode {
+        return Ok(false);
+    }
+    match new_mode {
+        #[cfg(unix)]
+        Some(new_mode) => {
+            use std::os::unix::fs::PermissionsExt;
+            let permissions = fs::Permissions::from_mode(new_mode);
+            map_io_err(fs::set_permissions(project, permissions), project)?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Updates a project generated from the `old` template to what the `new` one generates,
+/// while keeping the changes made in the project since then.
+/// Both templates need to have their variables set, usually to the same values.
+/// Files changed on both sides are merged with a three-way merge and conflicts are marked
+/// in the files rather than overwriting anything. The modes changed in the template are
+/// applied to the files whose mode wasn't changed in the project. Hooks are not run.
+///
+/// Secrets are not recorded, so both templates use the value of their environment variable or
+/// their default instead. Changes of the template next to a secret in a file will then be
+/// conflicts, unless the environment variable has the value used in the project.
+pub fn update(project_dir: &Path, old: &Template, new: &Template) -> Result<UpdateReport> {
+    let old_dir = tempdir()?;
+    old.generate(old_dir.path())?;
+    let new_dir = tempdir()?;
+    new.generate(new_dir.path())?;
+    // Used as base for files added in the new template that already exist in the project
+    let empty_dir = tempdir()?;
+    let empty = empty_dir.path().join("empty");
+    map_io_err(fs::write(&empty, ""), &empty)?;
+
+    let old_files = list_files(old_dir.path());
+    let new_files = list_files(new_dir.path());
+    let mut report = UpdateReport::default();
+
+    for relative in old_files.union(&new_files) {
+        let project_path = project_dir.join(relative);
+        let old_path = old_dir.path().join(relative);
+        let new_path = new_dir.path().join(relative);
+        let mode_updated = old_files.contains(relative)
+            && new_files.contains(relative)
+            && project_path.is_file()
+            && update_mode(&project_path, &old_path, &new_path)?;
+
+        match (old_files.contains(relative), new_files.contains(relative)) {
+            (true, false) => {
+                // Only delete it if it wasn't changed in the project
+                if project_path.is_file() {
+                    if read(&project_path)? == read(&old_path)? {
+                        map_io_err(fs::remove_file(&project_path), &project_path)?;
+                        report.removed.push(relative.clone());
+                    } else {
+                        report.conflicts.push(relative.clone());
+                    }
+                }
+                continue;
+            }
+            (false, true) if !project_path.exists() => {
+                copy(&new_path, &project_path)?;
+                report.added.push(relative.clone());
+                continue;
+            }
+            // Nothing changed in the content of the file in the template
+            (true, true) if read(&old_path)? == read(&new_path)? => {
+                if mode_updated {
+                    report.updated.push(relative.clone());
+                }
+                continue;
+            }
+            // Changed in the template but deleted in the project
+            (true, true) if !project_path.exists() => {
+                report.conflicts.push(relative.clone());
+                continue;
+            }
+            _ => {}
+        }
+
+        let current = read(&project_path)?;
+        let new_content = read(&new_path)?;
+        if current == new_content {
+            if mode_updated {
+                report.updated.push(relative.clone());
+            }
+            continue;
+        }
+        let base = if old_files.contains(relative) { old_path } else { empty.clone() };
+        let base_content = read(&base)?;
+        if current == base_content {
+            copy(&new_path, &project_path)?;
+            report.updated.push(relative.clone());
+            continue;
+        }
+        if is_binary(&current) || is_binary(&new_content) || is_binary(&base_content) {
+            report.conflicts.push(relative.clone());
+            continue;
+        }
+
+        let (merged, has_conflicts) = git::merge_file(&project_path, &base, &new_path)?;
+        map_io_err(fs::write(&project_path, merged), &project_path)?;
+        if has_conflicts {
+            report.conflicts.push(relative.clone());
+        } else {
+            report.updated.push(relative.clone());
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generation::tests::template_with;
+    use crate::Value;
+
+    fn create_template(dir: &Path, files: &[(&str, &str)]) -> Template {
+        let mut tpl = template_with(dir, "", files);
+        tpl.insert_variable("name", Value::String("world".to_string())).unwrap();
+        tpl
+    }
+
+    #[test]
+    fn can_update_project() {
+        let dir = tempdir().unwrap();
+        let project = dir.path().join("project");
+        let old = create_template(
+            &dir.path().join("old"),
+            &[
+                ("merged.md", "{{name}}\n1\n2\n3\n4\n5\n"),
+                ("conflict.md", "{{name}}\n"),
+                ("untouched.md", "old\n"),
+                ("removed.md", "removed\n"),
+                ("deleted.md", "old\n"),
+                ("modified_removed.md", "old\n"),
+                ("deleted_unchanged.md", "old\n"),
+            ],
+        );
+        old.generate(&project).unwrap();
+
+        // Changes made in the project
+        fs::write(project.join("merged.md"), "world\n1\n2\n3\n4\nfive\n").unwrap();
+        fs::write(project.join("conflict.md"), "project\n").unwrap();
+        fs::remove_file(project.join("deleted.md")).unwrap();
+        fs::write(project.join("modified_removed.md"), "project\n").unwrap();
+        fs::remove_file(project.join("deleted_unchanged.md")).unwrap();
+
+        let new = create_template(
+            &dir.path().join("new"),
+            &[
+                ("merged.md", "Hello {{name}}\n1\n2\n3\n4\n5\n"),
+                ("conflict.md", "template\n"),
+                ("untouched.md", "new\n"),
+                ("added.md", "{{name}}\n"),
+                ("deleted.md", "new\n"),
+                ("deleted_unchanged.md", "old\n"),
+            ],
+        );
+        let report = update(&project, &old, &new).unwrap();
+
+        assert_eq!(report.updated, vec![PathBuf::from("merged.md"), PathBuf::from("untouched.md")]);
+        assert_eq!(report.added, vec![PathBuf::from("added.md")]);
+        assert_eq!(report.removed, vec![PathBuf::from("removed.md")]);
+        assert_eq!(
+            report.conflicts,
+            vec![
+                PathBuf::from("conflict.md"),
+                PathBuf::from("deleted.md"),
+                PathBuf::from("modified_removed.md"),
+            ]
+        );
+
+        let read = |p: &str| fs::read_to_string(project.join(p)).unwrap();
+        assert_eq!(read("merged.md"), "Hello world\n1\n2\n3\n4\nfive\n");
+        assert_eq!(read("untouched.md"), "new\n");
+        assert_eq!(read("added.md"), "world\n");
+        assert!(read("conflict.md").contains("<<<<<<< project\nproject\n"));
+        assert!(!project.join("removed.md").exists());
+        assert!(!project.join("deleted.md").exists());
+        assert!(!project.join("deleted_unchanged.md").exists());
+        assert_eq!(read("modified_removed.md"), "project\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn can_update_file_modes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let project = dir.path().join("project");
+        let files = [("run.sh", "echo {{name}}\n"), ("kept.sh", "echo kept\n")];
+        let old = create_template(&dir.path().join("old"), &files);
+        old.generate(&project).unwrap();
+        // The project made that one executable already, with another mode
+        fs::set_permissions(project.join("kept.sh"), fs::Permissions::from_mode(0o700)).unwrap();
+
+        let new = create_template(&dir.path().join("new"), &files);
+        for name in ["run.sh", "kept.sh"] {
+            let path = dir.path().join("new").join(name);
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let report = update(&project, &old, &new).unwrap();
+
+        assert_eq!(report.updated, vec![PathBuf::from("run.sh")]);
+        let mode = |p: &str| fs::metadata(project.join(p)).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode("run.sh"), 0o755);
+        assert_eq!(mode("kept.sh"), 0o700);
+    }
+}
@@ -1,10 +1,12 @@
-use std::fs::{create_dir_all, File};
+use std::fs::{create_dir_all, File, Metadata};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 
 use memchr::memchr;
 use tera::{Context, Tera};
 
+use crate::archive::ArchiveFormat;
+use crate::config::{Config, TemplateAlias};
 use crate::errors::{map_io_err, new_error, ErrorKind, Result};
 use crate::filters::register_all_filters;
 
@@ -12,6 +14,8 @@ use crate::filters::register_all_filters;
 pub enum Source {
     Local(PathBuf),
     Git(String),
+    /// A local `.tar.gz`, `.tgz` or `.zip` file
+    Archive(PathBuf),
 }
 
 pub fn read_file(p: &Path) -> Result<String> {
@@ -38,10 +42,40 @@ pub fn create_directory(path: &Path) -> Result<()> {
     Ok(())
 }
 
-/// Is it a remote or a local thing
-pub fn get_source(input: &str) -> Source {
+/// The unix mode of a file, `None` on other platforms
+pub fn file_mode(metadata: &Metadata) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Some(metadata.permissions().mode() & 0o7777)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        None
+    }
+}
+
+/// Is it a remote or a local thing.
+/// Aliases from the config are resolved first, in which case the alias is returned as well
+/// since it can also set a ref and a directory.
+pub fn get_source<'a>(input: &str, config: &'a Config) -> (Source, Option<&'a TemplateAlias>) {
+    if let Some(alias) = config.get_alias(input) {
+        return (get_source_from_path(&alias.url), Some(alias));
+    }
+    (get_source_from_path(input), None)
+}
+
+fn get_source_from_path(input: &str) -> Source {
     let path = Path::new(input);
 
+    let archive_path = Path::new(input.strip_prefix("file://").unwrap_or(input));
+    if ArchiveFormat::from_path(archive_path).is_some()
+        && (archive_path.is_file() || input.starts_with("file://"))
+    {
+        return Source::Archive(archive_path.to_path_buf());
+    }
+
     if path.is_dir() {
         Source::Local(path.to_path_buf())
     } else {
@@ -79,6 +113,8 @@ mod tests {
         let folder2 = dir.path().join("also-working");
         fs::create_dir(&folder1).unwrap();
         fs::create_dir(&folder2).unwrap();
+        let archive = dir.path().join("template.tar.gz");
+        fs::write(&archive, "").unwrap();
         let mut inputs = vec![
             // Local valid
             (folder1.to_string_lossy().to_string(), Source::Local(folder1.to_path_buf())),
@@ -93,6 +129,14 @@ mod tests {
                 Source::Git("gitUser@git-server.local:git/Test".to_string()),
             ),
             ("git:git/Test".to_string(), Source::Git("git:git/Test".to_string())),
+            // Archives
+            (archive.to_string_lossy().to_string(), Source::Archive(archive.clone())),
+            (format!("file://{}", archive.display()), Source::Archive(archive.clone())),
+            (
+                "file:///not/there/template.zip".to_string(),
+                Source::Archive(PathBuf::from("/not/there/template.zip")),
+            ),
+            ("file:///some/repo".to_string(), Source::Git("file:///some/repo".to_string())),
             // Non existing local -> considered as a git and will fail later on
             ("hello".to_string(), Source::Git("hello".to_string())),
         ];
@@ -104,8 +148,38 @@ mod tests {
                 Source::Local(folder3.to_path_buf()),
             ));
         }
+        let config = Config::default();
         for (input, expected) in inputs {
-            assert_eq!(get_source(&input), expected);
+            assert_eq!(get_source(&input, &config), (expected, None));
         }
     }
+
+    #[test]
+    fn aliases_are_resolved_first() {
+        let dir = tempdir().unwrap();
+        let folder = dir.path().join("cli");
+        fs::create_dir(&folder).unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [templates.cli]
+            url = "https://github.com/Keats/rust-cli-template"
+            ref = "v1"
+
+            [templates.local]
+            url = "{}"
+            "#,
+            folder.display()
+        ))
+        .unwrap();
+
+        let (source, alias) = get_source("cli", &config);
+        assert_eq!(source, Source::Git("https://github.com/Keats/rust-cli-template".to_string()));
+        assert_eq!(alias.unwrap().git_ref.as_deref(), Some("v1"));
+        let (source, alias) = get_source("local", &config);
+        assert_eq!(source, Source::Local(folder));
+        assert!(alias.is_some());
+        let (source, alias) = get_source("other", &config);
+        assert_eq!(source, Source::Git("other".to_string()));
+        assert!(alias.is_none());
+    }
 }
@@ -1,4 +1,6 @@
+use semver::Version;
 use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use std::cmp::Ordering;
 use std::fmt::Formatter;
 use toml::Value as TomlValue;
 
@@ -9,6 +11,11 @@ pub enum Value {
     String(String),
     Integer(i64),
     Boolean(bool),
+    Float(f64),
+    /// A semver version, for variables with `type = "version"`. Rendered as a string
+    Version(Version),
+    /// The values selected in a multi-select question
+    Array(Vec<Value>),
 }
 
 impl std::fmt::Display for Value {
@@ -17,6 +24,12 @@ impl std::fmt::Display for Value {
             Value::Boolean(v) => write!(f, "{v}"),
             Value::String(v) => write!(f, "{v}"),
             Value::Integer(v) => write!(f, "{v}"),
+            Value::Float(v) => write!(f, "{v}"),
+            Value::Version(v) => write!(f, "{v}"),
+            Value::Array(v) => {
+                let items = v.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+                write!(f, "[{}]", items.join(", "))
+            }
         }
     }
 }
@@ -27,6 +40,9 @@ impl Value {
             Value::String(..) => "string",
             Value::Integer(..) => "integer",
             Value::Boolean(..) => "bool",
+            Value::Float(..) => "float",
+            Value::Version(..) => "version",
+            Value::Array(..) => "array",
         }
     }
 
@@ -40,6 +56,59 @@ impl Value {
             _ => None,
         }
     }
+
+    pub(crate) fn as_array(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Array(ref a) => Some(a),
+            _ => None,
+        }
+    }
+
+    /// Orders values of the same type, integers with floats and versions with strings that are
+    /// valid versions. Other values can't be ordered.
+    pub(crate) fn order(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+            (Value::Integer(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+            (Value::Float(a), Value::Integer(b)) => a.partial_cmp(&(*b as f64)),
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            (Value::Version(a), Value::Version(b)) => Some(a.cmp(b)),
+            (Value::Version(a), Value::String(b)) => Version::parse(b).ok().map(|b| a.cmp(&b)),
+            (Value::String(a), Value::Version(b)) => Version::parse(a).ok().map(|a| a.cmp(b)),
+            _ => None,
+        }
+    }
+
+    /// Converts strings to versions and integers to floats when `like` is one, including the
+    /// items of arrays. TOML and JSON have no versions and floats can be written as integers.
+    /// Values that can't be converted are returned as is.
+    pub(crate) fn coerce(self, like: &Value) -> Value {
+        match (self, like) {
+            (Value::String(s), Value::Version(_)) => match Version::parse(&s) {
+                Ok(v) => Value::Version(v),
+                Err(_) => Value::String(s),
+            },
+            (Value::Integer(i), Value::Float(_)) => Value::Float(i as f64),
+            (Value::Array(items), _) => {
+                Value::Array(items.into_iter().map(|i| i.coerce(like)).collect())
+            }
+            (value, _) => value,
+        }
+    }
+
+    fn from_toml(v: TomlValue, in_array: bool) -> Result<Value, String> {
+        match v {
+            TomlValue::String(s) => Ok(Value::String(s)),
+            TomlValue::Integer(i) => Ok(Value::Integer(i)),
+            TomlValue::Float(f) => Ok(Value::Float(f)),
+            TomlValue::Boolean(b) => Ok(Value::Boolean(b)),
+            TomlValue::Array(a) if !in_array => Ok(Value::Array(
+                a.into_iter().map(|i| Value::from_toml(i, true)).collect::<Result<_, _>>()?,
+            )),
+            _ => Err(format!("Value {} (of type `{}`) is not allowed as a value: only strings, integers, floats, boolean and arrays of those are.", v, v.type_str())),
+        }
+    }
 }
 
 impl<'de> Deserialize<'de> for Value {
@@ -48,11 +117,6 @@ impl<'de> Deserialize<'de> for Value {
         D: Deserializer<'de>,
     {
         let v: TomlValue = Deserialize::deserialize(deserializer)?;
-        match v {
-            TomlValue::String(s) => Ok(Value::String(s)),
-            TomlValue::Integer(i) => Ok(Value::Integer(i)),
-            TomlValue::Boolean(b) => Ok(Value::Boolean(b)),
-            _ => Err(D::Error::custom(format!("Value {} (of type `{}`) is not allowed as a value: only strings, integers and boolean are.", v, v.type_str()))),
-        }
+        Value::from_toml(v, false).map_err(D::Error::custom)
     }
 }